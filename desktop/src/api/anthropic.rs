@@ -0,0 +1,564 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::models::{AnthropicParams, AppError, ErrorKind, GenerationParams, HttpOptions, ImageAttachment, Message, ModelInfo, TokenUsage, ToolCall};
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::stream::{read_sse, StreamControl, StreamSink};
+use crate::api::tools::ToolSpec;
+
+const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MIN_THINKING_BUDGET: u32 = 1024;
+
+#[derive(Debug, Serialize, Clone)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    cache_type: &'static str,
+}
+
+const EPHEMERAL: CacheControl = CacheControl { cache_type: "ephemeral" };
+
+// A text, image, thinking, tool use or tool result content block
+#[derive(Debug, Serialize, Default)]
+struct AnthropicBlock {
+    #[serde(rename = "type")]
+    block_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    // thinking: the model's reasoning and the signature that lets it be sent back
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<AnthropicImageSource>,
+    // tool_use: the call's id, tool and arguments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    input: Option<serde_json::Value>,
+    // tool_result: the call answered and the tool's output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_use_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicImageSource {
+    #[serde(rename = "type")]
+    source_type: &'static str,
+    media_type: String,
+    data: String,
+}
+
+impl AnthropicBlock {
+    fn new(text: &str) -> Self {
+        Self {
+            block_type: "text",
+            text: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+    
+    fn image(image: &ImageAttachment) -> Self {
+        Self {
+            block_type: "image",
+            source: Some(AnthropicImageSource {
+                source_type: "base64",
+                media_type: image.mime_type.clone(),
+                data: image.data.clone(),
+            }),
+            ..Default::default()
+        }
+    }
+    
+    fn thinking(message: &Message) -> Self {
+        Self {
+            block_type: "thinking",
+            thinking: Some(message.reasoning.clone()),
+            signature: Some(message.reasoning_signature.clone()),
+            ..Default::default()
+        }
+    }
+    
+    fn tool_use(call: &ToolCall) -> Self {
+        Self {
+            block_type: "tool_use",
+            id: Some(call.id.clone()),
+            name: Some(call.name.clone()),
+            input: Some(call.arguments.clone()),
+            ..Default::default()
+        }
+    }
+    
+    fn tool_result(message: &Message) -> Self {
+        Self {
+            block_type: "tool_result",
+            tool_use_id: Some(message.tool_call_id.clone()),
+            content: Some(message.content.clone()),
+            ..Default::default()
+        }
+    }
+    
+    // Blocks of a message; thinking and images go first, as the API requires
+    // and recommends, and tool calls after the text. Only signed thinking
+    // from Claude can be sent back. The API rejects empty text blocks, so a
+    // message without text, images or tool calls (an answer stopped while
+    // thinking) has no blocks.
+    fn from_message(message: &Message) -> Vec<Self> {
+        if message.role == "tool" {
+            return vec![Self::tool_result(message)];
+        }
+        if message.content.is_empty() && message.images.is_empty() && message.tool_calls.is_empty() {
+            return Vec::new();
+        }
+        
+        let mut blocks: Vec<Self> = Vec::new();
+        if message.role == "assistant" && !message.reasoning_signature.is_empty() {
+            blocks.push(Self::thinking(message));
+        }
+        blocks.extend(message.images.iter().map(Self::image));
+        if !message.content.is_empty() {
+            blocks.push(Self::new(&message.content));
+        }
+        blocks.extend(message.tool_calls.iter().map(Self::tool_use));
+        blocks
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage {
+    role: String,
+    content: Vec<AnthropicBlock>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
+// Extended thinking, with the most tokens the model may think for
+#[derive(Debug, Serialize)]
+struct AnthropicThinking {
+    #[serde(rename = "type")]
+    thinking_type: &'static str,
+    budget_tokens: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<AnthropicBlock>,
+    messages: Vec<AnthropicMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<AnthropicTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<AnthropicThinking>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct AnthropicUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_creation_input_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+}
+
+// `input_tokens` excludes tokens written to or read from the prompt cache
+impl From<AnthropicUsage> for TokenUsage {
+    fn from(usage: AnthropicUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.input_tokens + usage.cache_creation_input_tokens + usage.cache_read_input_tokens,
+            completion_tokens: usage.output_tokens,
+            cached_tokens: usage.cache_read_input_tokens,
+            cost: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponseBlock {
+    #[serde(rename = "type")]
+    block_type: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    thinking: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    content: Vec<AnthropicResponseBlock>,
+    #[serde(default)]
+    usage: AnthropicUsage,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicError {
+    #[serde(rename = "type", default)]
+    error_type: String,
+    #[serde(default)]
+    message: String,
+}
+
+impl AnthropicError {
+    // Error types sent in a stream, which arrive after a 200 status
+    fn kind(&self) -> ErrorKind {
+        match self.error_type.as_str() {
+            "authentication_error" | "permission_error" => ErrorKind::Auth,
+            "not_found_error" => ErrorKind::ModelNotFound,
+            "rate_limit_error" => ErrorKind::RateLimit,
+            "api_error" | "overloaded_error" => ErrorKind::Server,
+            _ => ErrorKind::InvalidRequest,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicStartMessage {
+    #[serde(default)]
+    usage: AnthropicUsage,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicDelta {
+    TextDelta { text: String },
+    ThinkingDelta { thinking: String },
+    // Sent once at the end of a thinking block
+    SignatureDelta { signature: String },
+    // A fragment of the JSON arguments of the tool_use block being streamed
+    InputJsonDelta { partial_json: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStartBlock {
+    ToolUse { id: String, name: String },
+    #[serde(other)]
+    Other,
+}
+
+// Typed SSE events of the Messages API (the `data:` payload repeats the event type)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicStreamEvent {
+    MessageStart { message: AnthropicStartMessage },
+    ContentBlockStart { content_block: AnthropicStartBlock },
+    ContentBlockDelta { delta: AnthropicDelta },
+    MessageDelta {
+        #[serde(default)]
+        usage: AnthropicUsage,
+    },
+    MessageStop {},
+    Error { error: AnthropicError },
+    #[serde(other)]
+    Other,
+}
+
+// Build a Messages API request: system messages go in the separate `system`
+// field and consecutive turns from the same role are merged, since the API
+// requires user/assistant turns to alternate (tool results are sent as user
+// turns). The API has no seed parameter. With thinking on, the sampling
+// parameters are left out, since the API only accepts their defaults, and
+// `max_tokens`, which includes the thinking, is raised above the budget.
+fn build_request(params: &AnthropicParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], stream: bool) -> AnthropicRequest {
+    let mut system: Vec<AnthropicBlock> = Vec::new();
+    let mut anthropic_messages: Vec<AnthropicMessage> = Vec::new();
+    
+    for message in messages {
+        if message.role == "system" {
+            system.push(AnthropicBlock::new(&message.content));
+            continue;
+        }
+        
+        let blocks = AnthropicBlock::from_message(message);
+        if blocks.is_empty() {
+            continue;
+        }
+        
+        let role = if message.role == "assistant" { "assistant" } else { "user" };
+        match anthropic_messages.last_mut() {
+            Some(last) if last.role == role => last.content.extend(blocks),
+            _ => anthropic_messages.push(AnthropicMessage {
+                role: role.to_string(),
+                content: blocks,
+            }),
+        }
+    }
+    
+    // Prompt caching: a breakpoint on the last system block caches the system
+    // prompt, one on the last message caches the whole history as a prefix for
+    // the next turn
+    if params.cache_system_prompt {
+        if let Some(block) = system.last_mut() {
+            block.cache_control = Some(EPHEMERAL);
+        }
+    }
+    if params.cache_history {
+        if let Some(block) = anthropic_messages.last_mut().and_then(|m| m.content.last_mut()) {
+            block.cache_control = Some(EPHEMERAL);
+        }
+    }
+    
+    // The API's smallest budget is 1024 tokens
+    let thinking = generation
+        .thinking_tokens()
+        .filter(|&budget| budget > 0)
+        .map(|budget| AnthropicThinking {
+            thinking_type: "enabled",
+            budget_tokens: budget.max(MIN_THINKING_BUDGET),
+        });
+    let mut max_tokens = generation.max_tokens.unwrap_or(params.max_tokens);
+    if let Some(thinking) = &thinking {
+        if max_tokens <= thinking.budget_tokens {
+            max_tokens += thinking.budget_tokens;
+        }
+    }
+    let sampling = |value: Option<f64>| value.filter(|_| thinking.is_none());
+    
+    AnthropicRequest {
+        model: params.model.clone(),
+        max_tokens,
+        system,
+        messages: anthropic_messages,
+        stream,
+        temperature: sampling(generation.temperature),
+        top_p: sampling(generation.top_p),
+        stop_sequences: generation.stop.clone(),
+        tools: tools
+            .iter()
+            .map(|tool| AnthropicTool {
+                name: tool.name.clone(),
+                description: tool.description.clone(),
+                input_schema: tool.parameters.clone(),
+            })
+            .collect(),
+        thinking,
+    }
+}
+
+async fn send_request(client: &reqwest::Client, params: &AnthropicParams, request: &AnthropicRequest, sink: Option<&StreamSink>) -> Result<reqwest::Response, AppError> {
+    if params.api_key.is_empty() {
+        return Err(AppError::auth("Anthropic API key is required"));
+    }
+    
+    let url = format!("{}/messages", ANTHROPIC_API_BASE);
+    
+    let response = send_with_retry(
+        apply_http_options(client.post(&url), &params.http)
+            .header("x-api-key", &params.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .header("Content-Type", "application/json")
+            .json(request),
+        sink,
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to Anthropic: {}", e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Anthropic").await);
+    }
+    
+    Ok(response)
+}
+
+// Call Anthropic Messages API
+pub async fn call_anthropic(client: &reqwest::Client, params: &AnthropicParams, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
+    let request = build_request(params, messages, generation, &[], false);
+    let response = send_request(client, params, &request, None).await?;
+    
+    let anthropic_response: AnthropicResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Anthropic response: {}", e)))?;
+    
+    let content: String = anthropic_response
+        .content
+        .iter()
+        .filter(|block| block.block_type == "text")
+        .map(|block| block.text.as_str())
+        .collect();
+    let reasoning: String = anthropic_response
+        .content
+        .iter()
+        .filter(|block| block.block_type == "thinking")
+        .map(|block| block.thinking.as_str())
+        .collect();
+    
+    Ok(Completion {
+        content,
+        reasoning,
+        usage: Some(anthropic_response.usage.into()),
+        ..Default::default()
+    })
+}
+
+// Stream Anthropic Messages API response (typed SSE events), offering `tools`
+// to the model
+pub async fn stream_anthropic(client: &reqwest::Client, params: &AnthropicParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: &mut StreamSink) -> Result<(), AppError> {
+    let request = build_request(params, messages, generation, tools, true);
+    let response = send_request(client, params, &request, Some(sink)).await?;
+    
+    read_sse(response, sink, |sink, event| {
+        // The event name is redundant with the `type` field of the data payload
+        match serde_json::from_str::<AnthropicStreamEvent>(&event.data) {
+            Ok(AnthropicStreamEvent::ContentBlockDelta { delta: AnthropicDelta::TextDelta { text } }) => {
+                sink.push(&text)?;
+            }
+            Ok(AnthropicStreamEvent::ContentBlockDelta { delta: AnthropicDelta::ThinkingDelta { thinking } }) => {
+                sink.push_reasoning(&thinking);
+            }
+            Ok(AnthropicStreamEvent::ContentBlockDelta { delta: AnthropicDelta::SignatureDelta { signature } }) => {
+                sink.reasoning_signature.push_str(&signature);
+            }
+            // Blocks are streamed one at a time, so argument fragments belong
+            // to the last tool call started
+            Ok(AnthropicStreamEvent::ContentBlockStart { content_block: AnthropicStartBlock::ToolUse { id, name } }) => {
+                sink.start_tool_call(&id, &name);
+            }
+            Ok(AnthropicStreamEvent::ContentBlockDelta { delta: AnthropicDelta::InputJsonDelta { partial_json } }) => {
+                sink.push_tool_arguments(None, &partial_json);
+            }
+            // Input token counts arrive with message_start, the cumulative
+            // output count with message_delta
+            Ok(AnthropicStreamEvent::MessageStart { message }) => {
+                sink.usage = Some(message.usage.into());
+            }
+            Ok(AnthropicStreamEvent::MessageDelta { usage }) => {
+                sink.usage.get_or_insert_with(TokenUsage::default).completion_tokens = usage.output_tokens;
+            }
+            Ok(AnthropicStreamEvent::MessageStop {}) => return Ok(StreamControl::Done),
+            Ok(AnthropicStreamEvent::Error { error }) => {
+                return Err(AppError::new(error.kind(), format!("Anthropic stream error ({}): {}", error.error_type, error.message)));
+            }
+            _ => {}
+        }
+        
+        Ok(StreamControl::Continue)
+    }).await
+}
+
+// Every current Claude model has a 200k token context window and accepts
+// images and tools
+const ANTHROPIC_CONTEXT_LENGTH: u64 = 200_000;
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModel {
+    id: String,
+    #[serde(default)]
+    display_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModelsResponse {
+    data: Vec<AnthropicModel>,
+}
+
+// List models available to the API key
+pub async fn list_anthropic_models(client: &reqwest::Client, api_key: &str, http: &HttpOptions) -> Result<Vec<ModelInfo>, AppError> {
+    let url = format!("{}/models", ANTHROPIC_API_BASE);
+    
+    let response = apply_http_options(client.get(&url), http)
+        .header("x-api-key", api_key)
+        .header("anthropic-version", ANTHROPIC_VERSION)
+        .send()
+        .await
+        .map_err(|e| request_error(&e, format!("Failed to connect to Anthropic: {}", e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Anthropic").await);
+    }
+    
+    let models_response: AnthropicModelsResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Anthropic response: {}", e)))?;
+    
+    Ok(models_response.data
+        .into_iter()
+        .map(|m| ModelInfo {
+            display_name: if m.display_name.is_empty() { m.id.clone() } else { m.display_name },
+            id: m.id,
+            context_length: Some(ANTHROPIC_CONTEXT_LENGTH),
+            vision: Some(true),
+            tools: true,
+        })
+        .collect())
+}
+
+pub struct AnthropicProvider;
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn id(&self) -> &'static str {
+        "anthropic"
+    }
+    
+    fn display_name(&self) -> &'static str {
+        "Anthropic"
+    }
+    
+    fn reports_vision(&self) -> bool {
+        true
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn model(&self, config: &ProviderConfig) -> String {
+        config.params.anthropic.model.clone()
+    }
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        config.params.anthropic.model = model.to_string();
+    }
+    
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        if config.params.anthropic.api_key.is_empty() {
+            return Err(AppError::auth("Anthropic API key is required"));
+        }
+        if config.params.anthropic.model.trim().is_empty() {
+            return Err(AppError::config("Anthropic model is required"));
+        }
+        Ok(())
+    }
+    
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let generation = config.generation(&config.params.anthropic.generation);
+        call_anthropic(&config.client, &config.params.anthropic, messages, &generation).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let generation = config.generation(&config.params.anthropic.generation);
+        stream_anthropic(&config.client, &config.params.anthropic, messages, &generation, &config.tools, sink).await
+    }
+    
+    async fn list_models(&self, config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        list_anthropic_models(&config.client, &config.params.anthropic.api_key, &config.params.anthropic.http).await
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use crate::models::{AppError, ModelInfo};
+use crate::api::providers::load_provider_config;
+
+// How long a provider's model list is reused before it is fetched again
+const MODEL_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+// Model lists per provider setting ("ollama", "openai-compatible:groq", ...),
+// kept in Tauri state and cleared whenever settings are saved
+#[derive(Default)]
+pub struct ModelCatalog {
+    entries: Mutex<HashMap<String, (Instant, Vec<ModelInfo>)>>,
+}
+
+impl ModelCatalog {
+    fn get(&self, provider: &str) -> Option<Vec<ModelInfo>> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(provider)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < MODEL_CACHE_TTL)
+            .map(|(_, models)| models.clone())
+    }
+    
+    fn insert(&self, provider: &str, models: Vec<ModelInfo>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(provider.to_string(), (Instant::now(), models));
+        }
+    }
+    
+    pub fn clear(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+
+// List the models of a provider, served from the cache unless it is stale or
+// `refresh` is set
+pub async fn list_models(app: &AppHandle, provider: &str, refresh: bool) -> Result<Vec<ModelInfo>, AppError> {
+    if !refresh {
+        if let Some(models) = app.state::<ModelCatalog>().get(provider) {
+            return Ok(models);
+        }
+    }
+    
+    let (llm_provider, config) = load_provider_config(app, provider)?;
+    let models = llm_provider.list_models(&config).await?;
+    
+    app.state::<ModelCatalog>().insert(provider, models.clone());
+    Ok(models)
+}
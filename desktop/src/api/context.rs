@@ -0,0 +1,201 @@
+use tauri::AppHandle;
+
+use crate::models::{AppError, ContextPolicy, ContextSummary, Conversation, Message, UsageRecord};
+use crate::api::providers::{call_llm, CallOptions};
+use crate::storage::conversations::get_iso_timestamp;
+
+const SUMMARY_PROMPT: &str = "You maintain a running summary of a conversation between a user and an assistant. \
+Update the summary with the new messages. Keep every fact, decision, name, number and open question needed to \
+continue the conversation, drop pleasantries, and reply with the summary only.";
+
+const IMAGE_TOKENS: usize = 1000; // Typical cost of one image across vision models
+
+// Rough token estimate of a text, since providers don't expose their
+// tokenizers: about four characters per token
+pub fn estimate_text_tokens(text: &str) -> u64 {
+    (text.chars().count() / 4) as u64
+}
+
+// Token estimate of a message for the token budget: its text and attachments
+// plus a little overhead for the role
+pub fn estimate_tokens(message: &Message) -> u64 {
+    let files: u64 = message.files.iter().map(|file| file.tokens).sum();
+    estimate_text_tokens(&message.content) + (4 + message.images.len() * IMAGE_TOKENS) as u64 + files
+}
+
+// Text sent to the LLM for a message: attached documents first, each between
+// <document> tags naming the file (source code also fenced), then the message
+pub fn message_text(message: &Message) -> String {
+    if message.files.is_empty() {
+        return message.content.clone();
+    }
+    
+    let mut text = String::new();
+    for file in &message.files {
+        let content = match file.format.as_str() {
+            "text" | "markdown" | "pdf" | "docx" => file.content.clone(),
+            language => format!("```{}\n{}\n```", language, file.content),
+        };
+        text.push_str(&format!("<document name=\"{}\">\n{}\n</document>\n\n", file.name, content));
+    }
+    text.push_str(&message.content);
+    text
+}
+
+// Index of the first message of the last `turns` turns. The latest turn is
+// always kept, so the result never goes past the last user message.
+fn recent_start(messages: &[Message], turns: usize) -> usize {
+    let mut seen = 0;
+    for (i, message) in messages.iter().enumerate().rev() {
+        if message.role == "user" {
+            seen += 1;
+            if seen >= turns.max(1) {
+                return i;
+            }
+        }
+    }
+    0
+}
+
+// Start index of every turn after `from`, in order
+fn turn_starts(messages: &[Message], from: usize) -> Vec<usize> {
+    let mut starts = vec![from];
+    starts.extend(
+        messages
+            .iter()
+            .enumerate()
+            .skip(from + 1)
+            .filter(|(_, m)| m.role == "user")
+            .map(|(i, _)| i),
+    );
+    starts
+}
+
+// Messages of a conversation to send under `policy`. Dropped messages are
+// always taken from the oldest turns first, pinned messages are always kept,
+// and a summary, when the policy uses one, is sent as a system message in
+// place of the messages it covers.
+pub fn select_messages(conversation: &Conversation, policy: &ContextPolicy) -> Vec<Message> {
+    let messages = &conversation.messages;
+    
+    // Messages before `start` are sent only if pinned
+    let (start, summary) = match policy {
+        ContextPolicy::All => (0, None),
+        ContextPolicy::LastTurns { turns } => (recent_start(messages, *turns), None),
+        ContextPolicy::TokenBudget { tokens } => (budget_start(messages, *tokens), None),
+        ContextPolicy::Summarize { .. } => match &conversation.summary {
+            Some(summary) if summary.covered <= messages.len() => (summary.covered, Some(summary)),
+            _ => (0, None),
+        },
+    };
+    
+    let mut selected: Vec<Message> = Vec::new();
+    
+    if let Some(summary) = summary {
+        selected.push(Message {
+            role: "system".to_string(),
+            content: format!("Summary of the earlier conversation:\n{}", summary.content),
+            ..Default::default()
+        });
+    }
+    
+    selected.extend(
+        messages
+            .iter()
+            .enumerate()
+            .filter(|(i, m)| *i >= start || m.pinned)
+            .map(|(_, m)| m.clone()),
+    );
+    
+    selected
+}
+
+// First message kept under a token budget: whole turns are dropped oldest
+// first until the estimate fits, never dropping the latest turn
+fn budget_start(messages: &[Message], budget: u64) -> usize {
+    let mut total: u64 = messages.iter().map(estimate_tokens).sum();
+    let starts = turn_starts(messages, 0);
+    let last_turn = recent_start(messages, 1);
+    
+    for window in starts.windows(2) {
+        if total <= budget || window[0] >= last_turn {
+            return window[0];
+        }
+        total -= messages[window[0]..window[1]]
+            .iter()
+            .filter(|m| !m.pinned)
+            .map(estimate_tokens)
+            .sum::<u64>();
+    }
+    
+    starts.last().copied().unwrap_or(0).min(last_turn)
+}
+
+// Fold turns that fell out of the last `keep_turns` turns into the
+// conversation's summary using the conversation's own provider. Turns are
+// folded in batches of `keep_turns` so the summary isn't rewritten on every
+// message; between `keep_turns` and twice that many turns are sent verbatim.
+pub async fn update_summary(app: &AppHandle, conversation: &mut Conversation, keep_turns: usize, options: &CallOptions) -> Result<(), AppError> {
+    let messages = &conversation.messages;
+    
+    // Start over if messages the summary covered were removed
+    let (previous, covered) = match &conversation.summary {
+        Some(summary) if summary.covered <= messages.len() => (summary.content.as_str(), summary.covered),
+        _ => ("", 0),
+    };
+    
+    let start = recent_start(messages, keep_turns);
+    let pending_turns = messages[covered.min(start)..start]
+        .iter()
+        .filter(|m| m.role == "user")
+        .count();
+    if start <= covered || pending_turns < keep_turns.max(1) {
+        return Ok(());
+    }
+    
+    let transcript = messages[covered..start]
+        .iter()
+        .map(|m| (m, message_text(m)))
+        .filter(|(_, text)| !text.is_empty())
+        .map(|(m, text)| format!("{}: {}", m.role, text))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    
+    let request = if previous.is_empty() {
+        format!("New messages:\n\n{}", transcript)
+    } else {
+        format!("Current summary:\n{}\n\nNew messages:\n\n{}", previous, transcript)
+    };
+    
+    let summary_messages = vec![
+        Message {
+            role: "system".to_string(),
+            content: SUMMARY_PROMPT.to_string(),
+            ..Default::default()
+        },
+        Message {
+            role: "user".to_string(),
+            content: request,
+            ..Default::default()
+        },
+    ];
+    
+    let completion = call_llm(app, &summary_messages, options)
+        .await
+        .map_err(|e| format!("Failed to summarize conversation: {}", e))?;
+    
+    conversation.summary = Some(ContextSummary {
+        content: completion.content.trim().to_string(),
+        covered: start,
+    });
+    if let Some(usage) = completion.usage {
+        conversation.extra_usage.push(UsageRecord {
+            timestamp: get_iso_timestamp(),
+            provider: completion.provider,
+            model: completion.model,
+            usage,
+        });
+    }
+    
+    Ok(())
+}
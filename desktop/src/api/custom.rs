@@ -1,7 +1,310 @@
-use crate::models::Message;
+use async_trait::async_trait;
+use serde_json::Value;
+use crate::models::{AppError, CustomParams, CustomStreamFormat, GenerationParams, Message, TokenUsage};
+use crate::api::openai::ChatUsage;
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::stream::{read_ndjson, read_sse, StreamControl, StreamSink};
 
-// Call Custom API (placeholder - implement when needed)
-pub async fn call_custom(_url: &str, _messages: &[Message]) -> Result<String, String> {
-    Err("Custom API integration not yet implemented".to_string())
+// Look up a value by JSON pointer ("/a/0/b") or dot path ("a.0.b")
+pub fn lookup_path<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    if path.is_empty() {
+        return Some(value);
+    }
+    
+    if path.starts_with('/') {
+        value.pointer(path)
+    } else {
+        let pointer: String = path.split('.').map(|segment| format!("/{}", segment)).collect();
+        value.pointer(&pointer)
+    }
 }
 
+// Values available to the body template and header placeholders
+struct TemplateValues {
+    messages: Value,
+    model: String,
+    prompt: String,
+    stream: bool,
+    api_key: String,
+    generation: GenerationParams,
+}
+
+impl TemplateValues {
+    fn new(params: &CustomParams, messages: &[Message], generation: &GenerationParams, stream: bool) -> Self {
+        let messages_json = messages
+            .iter()
+            .map(|m| serde_json::json!({ "role": m.role, "content": m.content }))
+            .collect();
+        
+        let prompt = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == "user")
+            .map(|m| m.content.clone())
+            .unwrap_or_default();
+        
+        Self {
+            messages: Value::Array(messages_json),
+            model: params.model.clone(),
+            prompt,
+            stream,
+            api_key: params.api_key.clone(),
+            generation: generation.clone(),
+        }
+    }
+    
+    // A string that is exactly one placeholder is replaced by the JSON value
+    // itself (so "{{messages}}" becomes an array, "{{stream}}" a bool and an
+    // unset "{{temperature}}" null)
+    fn value_for(&self, placeholder: &str) -> Option<Value> {
+        match placeholder {
+            "{{messages}}" => Some(self.messages.clone()),
+            "{{model}}" => Some(Value::String(self.model.clone())),
+            "{{prompt}}" => Some(Value::String(self.prompt.clone())),
+            "{{stream}}" => Some(Value::Bool(self.stream)),
+            "{{temperature}}" => Some(serde_json::json!(self.generation.temperature)),
+            "{{top_p}}" => Some(serde_json::json!(self.generation.top_p)),
+            "{{max_tokens}}" => Some(serde_json::json!(self.generation.max_tokens)),
+            "{{stop}}" => Some(serde_json::json!(self.generation.stop)),
+            "{{seed}}" => Some(serde_json::json!(self.generation.seed)),
+            _ => None,
+        }
+    }
+    
+    // Replace placeholders inside a string in one pass over the template, so
+    // a substituted value (e.g. a prompt containing "{{api_key}}") is never
+    // scanned for placeholders itself
+    fn substitute_text(&self, text: &str) -> String {
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        
+        while let Some(start) = rest.find("{{") {
+            result.push_str(&rest[..start]);
+            let placeholder = &rest[start..];
+            let value = placeholder.find("}}").and_then(|end| {
+                let value = match &placeholder[2..end] {
+                    "model" => self.model.as_str(),
+                    "prompt" => self.prompt.as_str(),
+                    "stream" => if self.stream { "true" } else { "false" },
+                    "api_key" => self.api_key.as_str(),
+                    _ => return None,
+                };
+                Some((value, end + 2))
+            });
+            
+            match value {
+                Some((value, length)) => {
+                    result.push_str(value);
+                    rest = &placeholder[length..];
+                }
+                None => {
+                    result.push_str("{{");
+                    rest = &placeholder[2..];
+                }
+            }
+        }
+        
+        result.push_str(rest);
+        result
+    }
+    
+    fn render(&self, template: &Value) -> Value {
+        match template {
+            Value::String(s) => self
+                .value_for(s.trim())
+                .unwrap_or_else(|| Value::String(self.substitute_text(s))),
+            Value::Array(items) => Value::Array(items.iter().map(|item| self.render(item)).collect()),
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, val)| (key.clone(), self.render(val)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+fn parse_body_template(params: &CustomParams) -> Result<Value, AppError> {
+    serde_json::from_str(&params.body_template)
+        .map_err(|e| AppError::config(format!("Custom body template is not valid JSON: {}", e)))
+}
+
+fn parse_method(params: &CustomParams) -> Result<reqwest::Method, AppError> {
+    reqwest::Method::from_bytes(params.method.trim().to_uppercase().as_bytes())
+        .map_err(|_| AppError::config(format!("Invalid HTTP method: {}", params.method)))
+}
+
+// Build and send the templated request
+async fn send_custom_request(client: &reqwest::Client, params: &CustomParams, messages: &[Message], generation: &GenerationParams, stream: bool, sink: Option<&StreamSink>) -> Result<reqwest::Response, AppError> {
+    if params.url.trim().is_empty() {
+        return Err(AppError::config("Custom API URL is required"));
+    }
+    
+    let values = TemplateValues::new(params, messages, generation, stream);
+    let method = parse_method(params)?;
+    
+    // Placeholders are substituted in plain header values
+    let mut http = params.http.clone();
+    for value in http.headers.values_mut() {
+        *value = values.substitute_text(value);
+    }
+    let mut request = apply_http_options(client.request(method.clone(), &params.url), &http);
+    
+    // GET requests carry no body
+    if method != reqwest::Method::GET {
+        let body = values.render(&parse_body_template(params)?);
+        request = request.json(&body);
+    }
+    
+    let response = send_with_retry(request, sink)
+        .await
+        .map_err(|e| request_error(&e, format!("Failed to connect to custom API at {}: {}", params.url, e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Custom").await);
+    }
+    
+    Ok(response)
+}
+
+// Extract text at `path`, accepting strings as well as numbers/bools
+fn text_at(value: &Value, path: &str) -> Option<String> {
+    match lookup_path(value, path)? {
+        Value::String(s) => Some(s.clone()),
+        Value::Null => None,
+        other => Some(other.to_string()),
+    }
+}
+
+// Token usage at `usage-path`, if the response has one
+fn usage_at(value: &Value, path: &str) -> Option<TokenUsage> {
+    if path.is_empty() {
+        return None;
+    }
+    let usage = lookup_path(value, path).filter(|u| u.is_object())?;
+    serde_json::from_value::<ChatUsage>(usage.clone()).ok().map(TokenUsage::from)
+}
+
+// Call Custom API
+pub async fn call_custom(client: &reqwest::Client, params: &CustomParams, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
+    let response = send_custom_request(client, params, messages, generation, false, None).await?;
+    
+    let json: Value = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse custom API response: {}", e)))?;
+    
+    let content = text_at(&json, &params.response_path)
+        .ok_or_else(|| AppError::invalid_response(format!("No value at response path {} in custom API response", params.response_path)))?;
+    
+    Ok(Completion {
+        content,
+        usage: usage_at(&json, &params.usage_path),
+        ..Default::default()
+    })
+}
+
+// Stream Custom API response as NDJSON or SSE
+pub async fn stream_custom(client: &reqwest::Client, params: &CustomParams, messages: &[Message], generation: &GenerationParams, sink: &mut StreamSink) -> Result<(), AppError> {
+    let response = send_custom_request(client, params, messages, generation, true, Some(sink)).await?;
+    
+    match params.stream_format {
+        CustomStreamFormat::Sse => read_sse(response, sink, |sink, event| handle_stream_data(sink, params, &event.data)).await,
+        _ => read_ndjson(response, sink, |sink, line| handle_stream_data(sink, params, line)).await,
+    }
+}
+
+// Handle one streamed payload: an SSE event's data or an NDJSON line
+fn handle_stream_data(sink: &mut StreamSink, params: &CustomParams, data: &str) -> Result<StreamControl, AppError> {
+    if !params.done_marker.is_empty() && data == params.done_marker {
+        return Ok(StreamControl::Done);
+    }
+    
+    if let Ok(json) = serde_json::from_str::<Value>(data) {
+        if let Some(delta) = text_at(&json, &params.delta_path) {
+            sink.push(&delta)?;
+        }
+        if let Some(usage) = usage_at(&json, &params.usage_path) {
+            sink.usage = Some(usage);
+        }
+    }
+    
+    Ok(StreamControl::Continue)
+}
+
+pub struct CustomProvider;
+
+#[async_trait]
+impl LlmProvider for CustomProvider {
+    fn id(&self) -> &'static str {
+        "custom"
+    }
+    
+    fn display_name(&self) -> &'static str {
+        "Custom"
+    }
+    
+    // Requests are built from text templates
+    fn supports_images(&self) -> bool {
+        false
+    }
+    
+    fn model(&self, config: &ProviderConfig) -> String {
+        config.params.custom.model.clone()
+    }
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        config.params.custom.model = model.to_string();
+    }
+    
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        if config.params.custom.url.trim().is_empty() {
+            return Err(AppError::config("Custom API URL is required"));
+        }
+        parse_method(&config.params.custom)?;
+        parse_body_template(&config.params.custom)?;
+        Ok(())
+    }
+    
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let generation = config.generation(&config.params.custom.generation);
+        call_custom(&config.client, &config.params.custom, messages, &generation).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let generation = config.generation(&config.params.custom.generation);
+        if config.params.custom.stream_format == CustomStreamFormat::None {
+            let completion = call_custom(&config.client, &config.params.custom, messages, &generation).await?;
+            sink.usage = completion.usage;
+            return sink.push(&completion.content);
+        }
+        stream_custom(&config.client, &config.params.custom, messages, &generation, sink).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn values(prompt: &str) -> TemplateValues {
+        TemplateValues {
+            messages: Value::Array(Vec::new()),
+            model: "m".to_string(),
+            prompt: prompt.to_string(),
+            stream: true,
+            api_key: "secret".to_string(),
+            generation: GenerationParams::default(),
+        }
+    }
+    
+    #[test]
+    fn substitute_text_replaces_placeholders_once() {
+        let values = values("say {{api_key}} and {{model}}");
+        assert_eq!(
+            values.substitute_text("Bearer {{api_key}}: {{prompt}} ({{model}}, {{stream}}) {{other}} {{"),
+            "Bearer secret: say {{api_key}} and {{model}} (m, true) {{other}} {{"
+        );
+    }
+}
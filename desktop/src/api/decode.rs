@@ -0,0 +1,422 @@
+// Byte-level decoders for streamed responses. Chunks from the network can end
+// anywhere, including inside a multi-byte character, so bytes are buffered
+// until a whole line is available and only complete lines are turned into text.
+
+// A decoder turning response chunks into items for `stream::read_stream`
+pub trait StreamDecoder: Default {
+    type Item;
+    
+    // Feed a chunk and collect the items it completes
+    fn push(&mut self, chunk: &[u8], items: &mut Vec<Self::Item>);
+    
+    // Collect what is left once the response ends
+    fn finish(&mut self, items: &mut Vec<Self::Item>);
+}
+
+// Splits bytes into lines ended by LF, CRLF or a lone CR
+#[derive(Default)]
+pub struct LineDecoder {
+    buffer: Vec<u8>,
+    skip_lf: bool, // The previous chunk ended with CR, so a leading LF belongs to it
+}
+
+impl LineDecoder {
+    // Feed a chunk and collect the lines it completes, without their terminators
+    pub fn push(&mut self, chunk: &[u8], lines: &mut Vec<String>) {
+        let mut start = 0;
+        
+        for (i, &byte) in chunk.iter().enumerate() {
+            if self.skip_lf {
+                self.skip_lf = false;
+                if byte == b'\n' {
+                    start = i + 1;
+                    continue;
+                }
+            }
+            
+            if byte == b'\n' || byte == b'\r' {
+                self.buffer.extend_from_slice(&chunk[start..i]);
+                lines.push(String::from_utf8_lossy(&self.buffer).into_owned());
+                self.buffer.clear();
+                self.skip_lf = byte == b'\r';
+                start = i + 1;
+            }
+        }
+        
+        self.buffer.extend_from_slice(&chunk[start..]);
+    }
+    
+    // The final line when the stream ends without a terminator
+    pub fn finish(&mut self) -> Option<String> {
+        self.skip_lf = false;
+        if self.buffer.is_empty() {
+            return None;
+        }
+        let line = String::from_utf8_lossy(&self.buffer).into_owned();
+        self.buffer.clear();
+        Some(line)
+    }
+}
+
+// One server-sent event. `event` is empty when the server didn't name it
+// (the default type "message").
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+// Server-sent events decoder: `data:` lines of one event are joined with
+// newlines, comments (lines starting with `:`) and unknown fields are
+// ignored, and an event is dispatched on a blank line or when the stream
+// ends. An event with no `data` line is dropped, while a bare `data` line
+// gives an event with empty data.
+#[derive(Default)]
+pub struct SseDecoder {
+    lines: LineDecoder,
+    event: String,
+    data: Vec<String>,
+    pending: Vec<String>,
+}
+
+impl StreamDecoder for SseDecoder {
+    type Item = SseEvent;
+    
+    fn push(&mut self, chunk: &[u8], events: &mut Vec<SseEvent>) {
+        self.lines.push(chunk, &mut self.pending);
+        for line in std::mem::take(&mut self.pending) {
+            self.handle_line(&line, events);
+        }
+    }
+    
+    // Dispatch an event left unterminated when the stream ends, since some
+    // servers close the connection right after the last `data:`
+    fn finish(&mut self, events: &mut Vec<SseEvent>) {
+        if let Some(line) = self.lines.finish() {
+            self.handle_line(&line, events);
+        }
+        self.dispatch(events);
+    }
+}
+
+impl SseDecoder {
+    fn handle_line(&mut self, line: &str, events: &mut Vec<SseEvent>) {
+        if line.is_empty() {
+            self.dispatch(events);
+            return;
+        }
+        
+        if line.starts_with(':') {
+            return;
+        }
+        
+        // A single space after the colon is not part of the value
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => self.data.push(value.to_string()),
+            _ => {} // `id` and `retry` only matter to reconnecting clients
+        }
+    }
+    
+    fn dispatch(&mut self, events: &mut Vec<SseEvent>) {
+        let event = std::mem::take(&mut self.event);
+        if self.data.is_empty() {
+            return;
+        }
+        events.push(SseEvent {
+            event,
+            data: std::mem::take(&mut self.data).join("\n"),
+        });
+    }
+}
+
+// Newline-delimited JSON decoder, yielding each non-blank line trimmed
+#[derive(Default)]
+pub struct NdjsonDecoder {
+    lines: LineDecoder,
+    pending: Vec<String>,
+}
+
+impl StreamDecoder for NdjsonDecoder {
+    type Item = String;
+    
+    fn push(&mut self, chunk: &[u8], values: &mut Vec<String>) {
+        self.lines.push(chunk, &mut self.pending);
+        values.extend(self.pending.drain(..).filter_map(non_blank));
+    }
+    
+    // The final line when the stream ends without a newline
+    fn finish(&mut self, values: &mut Vec<String>) {
+        values.extend(self.lines.finish().and_then(non_blank));
+    }
+}
+
+fn non_blank(line: String) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.is_empty() {
+        None
+    } else if trimmed.len() == line.len() {
+        Some(line)
+    } else {
+        Some(trimmed.to_string())
+    }
+}
+
+// Splits text streamed by models that write their reasoning inline between
+// <think> and </think> into reasoning and answer. A tag may be split across
+// chunks, so text that could be the start of a tag is held back until the
+// next chunk shows whether it is one.
+#[derive(Default)]
+pub struct ThinkTagSplitter {
+    thinking: bool,
+    pending: String,
+    trim: bool, // Drop the whitespace models put after a tag
+}
+
+// A piece of streamed text: reasoning or answer
+#[derive(Debug, PartialEq)]
+pub enum TextPart {
+    Reasoning(String),
+    Answer(String),
+}
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+impl ThinkTagSplitter {
+    // Feed a chunk of text and collect the parts it completes
+    pub fn push(&mut self, chunk: &str, parts: &mut Vec<TextPart>) {
+        self.pending.push_str(chunk);
+        
+        loop {
+            let tag = if self.thinking { THINK_CLOSE } else { THINK_OPEN };
+            match self.pending.find(tag) {
+                Some(start) => {
+                    let text: String = self.pending.drain(..start + tag.len()).collect();
+                    self.emit(&text[..start], parts);
+                    self.thinking = !self.thinking;
+                    self.trim = true;
+                }
+                None => {
+                    // Keep a trailing prefix of the tag for the next chunk
+                    let keep = (1..tag.len())
+                        .rev()
+                        .find(|&len| self.pending.ends_with(&tag[..len]))
+                        .unwrap_or(0);
+                    let text: String = self.pending.drain(..self.pending.len() - keep).collect();
+                    self.emit(&text, parts);
+                    return;
+                }
+            }
+        }
+    }
+    
+    // Collect the text held back once the stream ends
+    pub fn finish(&mut self, parts: &mut Vec<TextPart>) {
+        let text = std::mem::take(&mut self.pending);
+        self.emit(&text, parts);
+    }
+    
+    fn emit(&mut self, text: &str, parts: &mut Vec<TextPart>) {
+        let text = if self.trim { text.trim_start() } else { text };
+        if text.is_empty() {
+            return;
+        }
+        self.trim = false;
+        parts.push(if self.thinking {
+            TextPart::Reasoning(text.to_string())
+        } else {
+            TextPart::Answer(text.to_string())
+        });
+    }
+}
+
+// Reasoning and answer of a whole response that may contain <think> tags
+pub fn split_think_tags(text: &str) -> (String, String) {
+    let mut splitter = ThinkTagSplitter::default();
+    let mut parts = Vec::new();
+    splitter.push(text, &mut parts);
+    splitter.finish(&mut parts);
+    
+    let mut reasoning = String::new();
+    let mut answer = String::new();
+    for part in parts {
+        match part {
+            TextPart::Reasoning(text) => reasoning.push_str(&text),
+            TextPart::Answer(text) => answer.push_str(&text),
+        }
+    }
+    (reasoning.trim_end().to_string(), answer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn sse(chunks: &[&[u8]]) -> Vec<SseEvent> {
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        for chunk in chunks {
+            decoder.push(chunk, &mut events);
+        }
+        decoder.finish(&mut events);
+        events
+    }
+    
+    fn ndjson(chunks: &[&[u8]]) -> Vec<String> {
+        let mut decoder = NdjsonDecoder::default();
+        let mut values = Vec::new();
+        for chunk in chunks {
+            decoder.push(chunk, &mut values);
+        }
+        decoder.finish(&mut values);
+        values
+    }
+    
+    fn data(event: &str, data: &str) -> SseEvent {
+        SseEvent {
+            event: event.to_string(),
+            data: data.to_string(),
+        }
+    }
+    
+    #[test]
+    fn sse_openai_chunks() {
+        // Recorded from a chat completion stream, split where the network split it
+        let events = sse(&[
+            b"data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\nda",
+            b"ta: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n",
+            b"\ndata: [DONE]\n\n",
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                data("", "{\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}"),
+                data("", "{\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}"),
+                data("", "[DONE]"),
+            ]
+        );
+    }
+    
+    #[test]
+    fn sse_multibyte_character_split_across_chunks() {
+        let text = "data: {\"text\":\"héllo 👋\"}\n\n".as_bytes();
+        // Split inside "é" and inside the emoji
+        let e = text.iter().position(|&b| b == 0xC3).unwrap() + 1;
+        let wave = text.iter().position(|&b| b == 0xF0).unwrap() + 2;
+        let events = sse(&[&text[..e], &text[e..wave], &text[wave..]]);
+        assert_eq!(events, vec![data("", "{\"text\":\"héllo 👋\"}")]);
+    }
+    
+    #[test]
+    fn sse_anthropic_named_events() {
+        let events = sse(&[
+            b"event: message_start\ndata: {\"type\":\"message_start\"}\n\n",
+            b"event: ping\ndata: {\"type\": \"ping\"}\n\nevent: content_block_delta\n",
+            b"data: {\"type\":\"content_block_delta\"}\n\n",
+        ]);
+        assert_eq!(
+            events,
+            vec![
+                data("message_start", "{\"type\":\"message_start\"}"),
+                data("ping", "{\"type\": \"ping\"}"),
+                data("content_block_delta", "{\"type\":\"content_block_delta\"}"),
+            ]
+        );
+    }
+    
+    #[test]
+    fn sse_multi_line_data_comments_and_crlf() {
+        let events = sse(&[
+            b": keep-alive\r\n\r\n",
+            b"data: first\r",
+            b"\ndata:second\r\nid: 7\r\nretry: 100\r\n\r\n",
+            b"data\n\n",
+        ]);
+        assert_eq!(events, vec![data("", "first\nsecond"), data("", "")]);
+    }
+    
+    #[test]
+    fn sse_lone_cr_line_endings() {
+        let events = sse(&[b"data: a\r\rdata: b\r", b"\r"]);
+        assert_eq!(events, vec![data("", "a"), data("", "b")]);
+    }
+    
+    #[test]
+    fn sse_final_unterminated_event() {
+        let events = sse(&[b"data: {\"done\":false}\n\ndata: {\"done\":", b"true}"]);
+        assert_eq!(events, vec![data("", "{\"done\":false}"), data("", "{\"done\":true}")]);
+    }
+    
+    #[test]
+    fn sse_event_without_data_is_not_dispatched() {
+        let events = sse(&[b"event: ping\n\n: comment only\n\n"]);
+        assert!(events.is_empty());
+    }
+    
+    #[test]
+    fn ndjson_ollama_chunks() {
+        // Recorded from /api/chat, including a character split between chunks
+        let body = "{\"message\":{\"content\":\"Grüße\"},\"done\":false}\n{\"done\":true,\"eval_count\":2}\n".as_bytes();
+        let split = body.iter().position(|&b| b == 0xC3).unwrap() + 1;
+        let values = ndjson(&[&body[..split], &body[split..]]);
+        assert_eq!(
+            values,
+            vec![
+                "{\"message\":{\"content\":\"Grüße\"},\"done\":false}".to_string(),
+                "{\"done\":true,\"eval_count\":2}".to_string(),
+            ]
+        );
+    }
+    
+    #[test]
+    fn ndjson_blank_lines_crlf_and_final_line() {
+        let values = ndjson(&[b"{\"a\":1}\r\n\r\n  \n", b" {\"b\":2} \r\n{\"c\"", b":3}"]);
+        assert_eq!(values, vec!["{\"a\":1}", "{\"b\":2}", "{\"c\":3}"]);
+    }
+    
+    #[test]
+    fn line_decoder_crlf_split_across_chunks() {
+        let mut decoder = LineDecoder::default();
+        let mut lines = Vec::new();
+        decoder.push(b"one\r", &mut lines);
+        decoder.push(b"\ntwo\r", &mut lines);
+        decoder.push(b"\n", &mut lines);
+        assert_eq!(lines, vec!["one", "two"]);
+        assert_eq!(decoder.finish(), None);
+    }
+    
+    #[test]
+    fn think_tags_split_across_chunks() {
+        let mut splitter = ThinkTagSplitter::default();
+        let mut parts = Vec::new();
+        for chunk in ["<thi", "nk>\nLet me ", "see.</th", "ink>\n\nHello", " <b>there</b>"] {
+            splitter.push(chunk, &mut parts);
+        }
+        splitter.finish(&mut parts);
+        assert_eq!(
+            parts,
+            vec![
+                TextPart::Reasoning("Let me ".to_string()),
+                TextPart::Reasoning("see.".to_string()),
+                TextPart::Answer("Hello".to_string()),
+                TextPart::Answer(" <b>there</b>".to_string()),
+            ]
+        );
+    }
+    
+    #[test]
+    fn think_tags_absent_or_unfinished() {
+        assert_eq!(split_think_tags("Just an answer <"), (String::new(), "Just an answer <".to_string()));
+        assert_eq!(split_think_tags("<think>Still thinking"), ("Still thinking".to_string(), String::new()));
+        assert_eq!(
+            split_think_tags("<think>\nHmm\n</think>\n\nAnswer"),
+            ("Hmm".to_string(), "Answer".to_string())
+        );
+    }
+}
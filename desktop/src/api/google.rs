@@ -1,7 +1,595 @@
-use crate::models::Message;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::models::{AppError, Citation, ErrorKind, GenerationParams, GoogleParams, HttpOptions, Message, ModelInfo, TokenUsage, ToolCall};
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::stream::{read_sse, StreamControl, StreamSink};
+use crate::api::tools::{flatten_tool_messages, ToolSpec};
 
-// Call Google API (placeholder - implement when needed)
-pub async fn call_google(_api_key: &str, _messages: &[Message]) -> Result<String, String> {
-    Err("Google API integration not yet implemented".to_string())
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct GeminiPart {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    text: String,
+    // Whether the text is a summary of the model's thinking
+    #[serde(default, skip_serializing_if = "is_false")]
+    thought: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inline_data: Option<GeminiInlineData>,
+    #[serde(rename = "functionCall", default, skip_serializing_if = "Option::is_none")]
+    function_call: Option<GeminiFunctionCall>,
+    #[serde(rename = "functionResponse", default, skip_serializing_if = "Option::is_none")]
+    function_response: Option<GeminiFunctionResponse>,
+}
+
+// Gemini gives function calls no id; results are matched to calls by name
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct GeminiFunctionCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct GeminiFunctionResponse {
+    name: String,
+    response: serde_json::Value,
+}
+
+// An image sent inline with the request
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct GeminiInlineData {
+    mime_type: String,
+    data: String,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl GeminiPart {
+    fn text(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            ..Default::default()
+        }
+    }
+    
+    // Parts of a message: its text, then its images and function calls. A
+    // tool result is a function response, whose content must be an object.
+    fn from_message(message: &Message) -> Vec<Self> {
+        if message.role == "tool" {
+            return vec![Self {
+                function_response: Some(GeminiFunctionResponse {
+                    name: message.tool_name.clone(),
+                    response: serde_json::json!({ "result": message.content }),
+                }),
+                ..Default::default()
+            }];
+        }
+        
+        let mut parts = Vec::new();
+        if !message.content.is_empty() {
+            parts.push(Self::text(&message.content));
+        }
+        parts.extend(message.images.iter().map(|image| Self {
+            inline_data: Some(GeminiInlineData {
+                mime_type: image.mime_type.clone(),
+                data: image.data.clone(),
+            }),
+            ..Default::default()
+        }));
+        parts.extend(message.tool_calls.iter().map(|call| Self {
+            function_call: Some(GeminiFunctionCall {
+                name: call.name.clone(),
+                args: call.arguments.clone(),
+            }),
+            ..Default::default()
+        }));
+        parts
+    }
+}
+
+// Gemini takes an OpenAPI subset of JSON Schema; remove the keywords outside it
+fn openapi_schema(schema: &serde_json::Value) -> serde_json::Value {
+    match schema {
+        serde_json::Value::Object(map) => map
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "$schema" | "$id" | "$ref" | "$defs" | "additionalProperties"))
+            .map(|(key, value)| (key.clone(), openapi_schema(value)))
+            .collect(),
+        serde_json::Value::Array(items) => items.iter().map(openapi_schema).collect(),
+        value => value.clone(),
+    }
+}
+
+// Function declarations for the tools; a tool without arguments gets no
+// schema, since Gemini rejects an object schema without properties
+fn function_declarations(tools: &[ToolSpec]) -> serde_json::Value {
+    let declarations: Vec<serde_json::Value> = tools
+        .iter()
+        .map(|tool| {
+            let mut declaration = serde_json::json!({
+                "name": tool.name,
+                "description": tool.description,
+            });
+            if tool.has_parameters() {
+                declaration["parameters"] = openapi_schema(&tool.parameters);
+            }
+            declaration
+        })
+        .collect();
+    
+    serde_json::json!({ "functionDeclarations": declarations })
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct GeminiContent {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    role: String,
+    #[serde(default)]
+    parts: Vec<GeminiPart>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<GeminiContent>,
+    contents: Vec<GeminiContent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    generation_config: Option<GeminiGenerationConfig>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop_sequences: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking_config: Option<GeminiThinkingConfig>,
 }
 
+// Thinking budget in tokens (0 turns thinking off where the model allows it),
+// and whether to return summaries of the thoughts
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiThinkingConfig {
+    thinking_budget: u32,
+    include_thoughts: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
+struct GeminiWebSource {
+    #[serde(default)]
+    uri: String,
+    #[serde(default)]
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiGroundingChunk {
+    #[serde(default)]
+    web: Option<GeminiWebSource>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiGroundingMetadata {
+    #[serde(default)]
+    grounding_chunks: Vec<GeminiGroundingChunk>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiCandidate {
+    #[serde(default)]
+    content: Option<GeminiContent>,
+    #[serde(default)]
+    grounding_metadata: Option<GeminiGroundingMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiPromptFeedback {
+    #[serde(default)]
+    block_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiUsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    #[serde(default)]
+    thoughts_token_count: u64,
+    #[serde(default)]
+    cached_content_token_count: u64,
+}
+
+// Thinking tokens are billed as output
+impl From<&GeminiUsageMetadata> for TokenUsage {
+    fn from(usage: &GeminiUsageMetadata) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_token_count,
+            completion_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+            cached_tokens: usage.cached_content_token_count,
+            cost: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiResponse {
+    #[serde(default)]
+    candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    prompt_feedback: Option<GeminiPromptFeedback>,
+    #[serde(default)]
+    usage_metadata: Option<GeminiUsageMetadata>,
+}
+
+impl GeminiResponse {
+    // Text of the answer parts, or of the thought summaries
+    fn text_parts(&self, thought: bool) -> String {
+        self.candidates
+            .first()
+            .and_then(|c| c.content.as_ref())
+            .map(|c| c.parts.iter().filter(|p| p.thought == thought).map(|p| p.text.as_str()).collect())
+            .unwrap_or_default()
+    }
+    
+    fn text(&self) -> String {
+        self.text_parts(false)
+    }
+    
+    fn thoughts(&self) -> String {
+        self.text_parts(true)
+    }
+    
+    fn function_calls(&self) -> Vec<ToolCall> {
+        self.candidates
+            .first()
+            .and_then(|c| c.content.as_ref())
+            .map(|c| {
+                c.parts
+                    .iter()
+                    .filter_map(|p| p.function_call.as_ref())
+                    .map(|call| ToolCall {
+                        name: call.name.clone(),
+                        arguments: call.args.clone(),
+                        ..Default::default()
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    
+    fn citations(&self) -> Vec<Citation> {
+        self.candidates
+            .first()
+            .and_then(|c| c.grounding_metadata.as_ref())
+            .map(|m| {
+                m.grounding_chunks
+                    .iter()
+                    .filter_map(|chunk| chunk.web.as_ref())
+                    .filter(|web| !web.uri.is_empty())
+                    .map(|web| Citation {
+                        url: web.uri.clone(),
+                        title: web.title.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    
+    fn usage(&self) -> Option<TokenUsage> {
+        self.usage_metadata.as_ref().map(TokenUsage::from)
+    }
+    
+    fn block_reason(&self) -> Option<&str> {
+        self.prompt_feedback.as_ref().and_then(|f| f.block_reason.as_deref())
+    }
+}
+
+// Build a Gemini request: system messages become the system instruction,
+// assistant turns use the "model" role (tool results the "user" role) and
+// consecutive turns from the same role are merged since Gemini expects
+// user/model turns to alternate
+fn build_request(params: &GoogleParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec]) -> GeminiRequest {
+    let mut system_parts: Vec<GeminiPart> = Vec::new();
+    let mut contents: Vec<GeminiContent> = Vec::new();
+    
+    // Gemini rejects function calls and responses in the history when no
+    // functions are declared, which is also the case with Google Search on
+    let declares_functions = !params.web_search_enabled && !tools.is_empty();
+    let has_tool_messages = messages.iter().any(|m| m.role == "tool" || !m.tool_calls.is_empty());
+    let flattened;
+    let messages = if declares_functions || !has_tool_messages {
+        messages
+    } else {
+        flattened = flatten_tool_messages(messages);
+        &flattened[..]
+    };
+    
+    for message in messages {
+        let role = match message.role.as_str() {
+            "system" => {
+                system_parts.push(GeminiPart::text(&message.content));
+                continue;
+            }
+            "assistant" => "model",
+            _ => "user",
+        };
+        
+        // An empty text part serializes as `{}`, which Gemini rejects, so a
+        // message with nothing to send (an answer stopped while thinking) is left out
+        let parts = GeminiPart::from_message(message);
+        if parts.is_empty() {
+            continue;
+        }
+        
+        match contents.last_mut() {
+            Some(last) if last.role == role => last.parts.extend(parts),
+            _ => contents.push(GeminiContent {
+                role: role.to_string(),
+                parts,
+            }),
+        }
+    }
+    
+    let system_instruction = if system_parts.is_empty() {
+        None
+    } else {
+        Some(GeminiContent {
+            role: String::new(),
+            parts: system_parts,
+        })
+    };
+    
+    // Google Search grounding, which can't be combined with function calling
+    let tools = if params.web_search_enabled {
+        Some(vec![serde_json::json!({ "google_search": {} })])
+    } else if declares_functions {
+        Some(vec![function_declarations(tools)])
+    } else {
+        None
+    };
+    
+    let generation_config = if generation.is_empty() {
+        None
+    } else {
+        Some(GeminiGenerationConfig {
+            temperature: generation.temperature,
+            top_p: generation.top_p,
+            max_output_tokens: generation.max_tokens,
+            stop_sequences: generation.stop.clone(),
+            seed: generation.seed,
+            thinking_config: generation.thinking_tokens().map(|budget| GeminiThinkingConfig {
+                thinking_budget: budget,
+                include_thoughts: budget > 0,
+            }),
+        })
+    };
+    
+    GeminiRequest {
+        system_instruction,
+        contents,
+        tools,
+        generation_config,
+    }
+}
+
+// Call Google Gemini API
+pub async fn call_google(client: &reqwest::Client, params: &GoogleParams, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
+    if params.api_key.is_empty() {
+        return Err(AppError::auth("Google API key is required"));
+    }
+    
+    let request = build_request(params, messages, generation, &[]);
+    let url = format!("{}/models/{}:generateContent", GEMINI_API_BASE, params.model);
+    
+    let response = send_with_retry(
+        apply_http_options(client.post(&url), &params.http)
+            .header("x-goog-api-key", &params.api_key)
+            .json(&request),
+        None,
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to Google: {}", e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Google").await);
+    }
+    
+    let gemini_response: GeminiResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Google response: {}", e)))?;
+    
+    if let Some(reason) = gemini_response.block_reason() {
+        return Err(AppError::new(ErrorKind::InvalidRequest, format!("Google blocked the prompt: {}", reason)));
+    }
+    
+    if gemini_response.candidates.is_empty() {
+        return Err(AppError::invalid_response("No response from Google"));
+    }
+    
+    Ok(Completion {
+        content: gemini_response.text(),
+        reasoning: gemini_response.thoughts(),
+        citations: gemini_response.citations(),
+        usage: gemini_response.usage(),
+        ..Default::default()
+    })
+}
+
+// Stream Google Gemini response, offering `tools` to the model
+pub async fn stream_google(client: &reqwest::Client, params: &GoogleParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: &mut StreamSink) -> Result<(), AppError> {
+    if params.api_key.is_empty() {
+        return Err(AppError::auth("Google API key is required"));
+    }
+    
+    let request = build_request(params, messages, generation, tools);
+    let url = format!("{}/models/{}:streamGenerateContent?alt=sse", GEMINI_API_BASE, params.model);
+    
+    let response = send_with_retry(
+        apply_http_options(client.post(&url), &params.http)
+            .header("x-goog-api-key", &params.api_key)
+            .json(&request),
+        Some(sink),
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to Google: {}", e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Google").await);
+    }
+    
+    // Stream the response (SSE format, one GenerateContentResponse per event)
+    read_sse(response, sink, |sink, event| {
+        if let Ok(chunk) = serde_json::from_str::<GeminiResponse>(&event.data) {
+            if let Some(reason) = chunk.block_reason() {
+                return Err(AppError::new(ErrorKind::InvalidRequest, format!("Google blocked the prompt: {}", reason)));
+            }
+            sink.push_reasoning(&chunk.thoughts());
+            sink.push(&chunk.text())?;
+            // Function calls arrive whole
+            for call in chunk.function_calls() {
+                sink.add_tool_call(call);
+            }
+            // Grounding metadata usually arrives with the final chunk
+            sink.add_citations(chunk.citations());
+            // Every chunk reports the running token counts
+            if let Some(usage) = chunk.usage() {
+                sink.usage = Some(usage);
+            }
+        }
+        
+        Ok(StreamControl::Continue)
+    }).await
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GeminiModel {
+    name: String,
+    #[serde(default)]
+    display_name: String,
+    #[serde(default)]
+    input_token_limit: Option<u64>,
+    #[serde(default)]
+    supported_generation_methods: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GeminiModelsResponse {
+    #[serde(default)]
+    models: Vec<GeminiModel>,
+}
+
+// List Gemini models that support content generation
+pub async fn list_google_models(client: &reqwest::Client, api_key: &str, http: &HttpOptions) -> Result<Vec<ModelInfo>, AppError> {
+    let url = format!("{}/models", GEMINI_API_BASE);
+    
+    let response = apply_http_options(client.get(&url), http)
+        .header("x-goog-api-key", api_key)
+        .send()
+        .await
+        .map_err(|e| request_error(&e, format!("Failed to connect to Google: {}", e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Google").await);
+    }
+    
+    let models_response: GeminiModelsResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Google response: {}", e)))?;
+    
+    Ok(models_response.models
+        .into_iter()
+        .filter(|m| m.supported_generation_methods.iter().any(|method| method == "generateContent"))
+        .map(|m| {
+            let id = m.name.trim_start_matches("models/");
+            // Gemini models are multimodal and support function calling;
+            // the Gemma models served through the same API are text-only
+            let gemini = id.starts_with("gemini");
+            ModelInfo {
+                id: id.to_string(),
+                display_name: if m.display_name.is_empty() { id.to_string() } else { m.display_name.clone() },
+                context_length: m.input_token_limit,
+                vision: Some(gemini),
+                tools: gemini,
+            }
+        })
+        .collect())
+}
+
+pub struct GoogleProvider;
+
+#[async_trait]
+impl LlmProvider for GoogleProvider {
+    fn id(&self) -> &'static str {
+        "google"
+    }
+    
+    fn display_name(&self) -> &'static str {
+        "Google"
+    }
+    
+    fn reports_vision(&self) -> bool {
+        true
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn model(&self, config: &ProviderConfig) -> String {
+        config.params.google.model.clone()
+    }
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        config.params.google.model = model.to_string();
+    }
+    
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        if config.params.google.api_key.is_empty() {
+            return Err(AppError::auth("Google API key is required"));
+        }
+        if config.params.google.model.trim().is_empty() {
+            return Err(AppError::config("Google model is required"));
+        }
+        Ok(())
+    }
+    
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let generation = config.generation(&config.params.google.generation);
+        call_google(&config.client, &config.params.google, messages, &generation).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let generation = config.generation(&config.params.google.generation);
+        stream_google(&config.client, &config.params.google, messages, &generation, &config.tools, sink).await
+    }
+    
+    async fn list_models(&self, config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        list_google_models(&config.client, &config.params.google.api_key, &config.params.google.http).await
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::sync::RwLock;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::models::{AppError, AuthMode, ErrorKind, HttpOptions, NetworkSettings};
+use crate::storage::settings::load_network_settings;
+
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+const DEFAULT_READ_TIMEOUT: u64 = 300; // Local models can take minutes to load before the first token
+const DEFAULT_IDLE_TIMEOUT: u64 = 90;
+
+// HTTP client shared by every provider request, kept in Tauri state so
+// connections are reused. It is rebuilt whenever the network settings change.
+pub struct HttpClient {
+    client: RwLock<reqwest::Client>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        let client = build_client(&NetworkSettings::default()).unwrap_or_default();
+        Self {
+            client: RwLock::new(client),
+        }
+    }
+}
+
+impl HttpClient {
+    // The current client; cloning is cheap since clients share their pool
+    pub fn get(&self) -> reqwest::Client {
+        match self.client.read() {
+            Ok(client) => client.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+    
+    // Replace the client, keeping the previous one if the settings are invalid
+    pub fn rebuild(&self, settings: &NetworkSettings) -> Result<(), AppError> {
+        let client = build_client(settings)?;
+        let mut current = self.client.write().map_err(|e| format!("Failed to lock HTTP client: {}", e))?;
+        *current = client;
+        Ok(())
+    }
+}
+
+// Rebuild the shared client from the saved network settings
+pub fn reload_http_client(app: &AppHandle) -> Result<(), AppError> {
+    let settings = load_network_settings(app)?;
+    app.state::<HttpClient>().rebuild(&settings)
+}
+
+fn read_file(path: &str, what: &str) -> Result<Vec<u8>, AppError> {
+    fs::read(path).map_err(|e| AppError::config(format!("Failed to read {} {}: {}", what, path, e)))
+}
+
+pub fn build_client(settings: &NetworkSettings) -> Result<reqwest::Client, AppError> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs.unwrap_or(DEFAULT_CONNECT_TIMEOUT)))
+        .read_timeout(Duration::from_secs(settings.read_timeout_secs.unwrap_or(DEFAULT_READ_TIMEOUT)))
+        .pool_idle_timeout(Duration::from_secs(settings.idle_timeout_secs.unwrap_or(DEFAULT_IDLE_TIMEOUT)));
+    
+    // Without an explicit proxy, reqwest follows HTTP(S)_PROXY and NO_PROXY
+    let proxy_url = settings.proxy.trim();
+    if !proxy_url.is_empty() {
+        let proxy = reqwest::Proxy::all(proxy_url)
+            .map_err(|e| AppError::config(format!("Invalid proxy URL {}: {}", proxy_url, e)))?
+            .no_proxy(reqwest::NoProxy::from_string(&settings.no_proxy));
+        builder = builder.proxy(proxy);
+    }
+    
+    for path in &settings.ca_certificates {
+        let certificates = reqwest::Certificate::from_pem_bundle(&read_file(path, "CA certificate")?)
+            .map_err(|e| AppError::config(format!("Invalid CA certificate {}: {}", path, e)))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    
+    // Client certificate for mutual TLS: a PEM certificate chain and its PKCS#8 PEM key
+    match (settings.client_certificate.trim(), settings.client_key.trim()) {
+        ("", "") => {}
+        ("", _) | (_, "") => {
+            return Err(AppError::config("Mutual TLS needs both a client certificate and a client key"));
+        }
+        (certificate_path, key_path) => {
+            let identity = reqwest::Identity::from_pkcs8_pem(
+                &read_file(certificate_path, "client certificate")?,
+                &read_file(key_path, "client key")?,
+            )
+            .map_err(|e| AppError::config(format!("Invalid client certificate or key: {}", e)))?;
+            builder = builder.identity(identity);
+        }
+    }
+    
+    builder.build().map_err(|e| AppError::config(format!("Failed to build HTTP client: {}", e)))
+}
+
+// Add a provider's extra headers and authentication to a request
+pub fn apply_http_options(request: reqwest::RequestBuilder, options: &HttpOptions) -> reqwest::RequestBuilder {
+    let mut request = request;
+    
+    for (name, value) in options.headers.iter().chain(&options.secret_headers) {
+        request = request.header(name.as_str(), value.as_str());
+    }
+    
+    match &options.auth {
+        AuthMode::None => request,
+        AuthMode::Bearer { token_key } => request.bearer_auth(token_key),
+        AuthMode::Basic { username, password_key } => request.basic_auth(username, Some(password_key)),
+        AuthMode::Header { name, value_key } if !name.is_empty() => request.header(name.as_str(), value_key.as_str()),
+        AuthMode::Header { .. } => request,
+    }
+}
+
+// Error for a request that got no response: the server is unreachable, timed
+// out, or the request couldn't be built from the settings
+pub fn request_error(error: &reqwest::Error, message: String) -> AppError {
+    let kind = if error.is_timeout() {
+        ErrorKind::Timeout
+    } else if error.is_builder() {
+        ErrorKind::Config
+    } else {
+        ErrorKind::Network
+    };
+    AppError::new(kind, message)
+}
+
+// Error for a provider response with a failure status, classified by the status
+pub async fn error_response(response: reqwest::Response, name: &str) -> AppError {
+    let status = response.status();
+    let error_text = response.text().await.unwrap_or_default();
+    AppError::from_status(status.as_u16(), format!("{} API error ({}): {}", name, status, error_text))
+}
@@ -1,10 +1,23 @@
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use crate::models::Message;
+use crate::models::{AppError, GenerationParams, HttpOptions, LMStudioParams, Message, ModelInfo, TokenUsage};
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::openai::{chat_reasoning, chat_tools, handle_chat_completion_event, stream_options, ChatContent, ChatSampling, ChatToolCall, ChatUsage};
+use crate::api::decode::split_think_tags;
+use crate::api::openai_compatible::fetch_model_list;
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::stream::{read_sse, StreamSink};
+use crate::api::tools::ToolSpec;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct OpenAIMessage {
     role: String,
-    content: String,
+    content: ChatContent,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ChatToolCall>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    tool_call_id: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -13,6 +26,11 @@ struct OpenAIRequest {
     messages: Vec<OpenAIMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
     tools: Option<Vec<serde_json::Value>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    #[serde(flatten)]
+    sampling: ChatSampling,
 }
 
 #[derive(Debug, Deserialize)]
@@ -23,54 +41,169 @@ struct OpenAIChoice {
 #[derive(Debug, Deserialize)]
 struct OpenAIResponse {
     choices: Vec<OpenAIChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
 }
 
 // Call LM Studio API (similar to Ollama)
-pub async fn call_lmstudio(url: &str, model: &str, messages: &[Message]) -> Result<String, String> {
+pub async fn call_lmstudio(client: &reqwest::Client, url: &str, model: &str, http: &HttpOptions, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
     // LM Studio uses OpenAI-compatible API
-    let client = reqwest::Client::new();
-    
     let openai_messages: Vec<OpenAIMessage> = messages
         .iter()
         .map(|m| OpenAIMessage {
             role: m.role.clone(),
-            content: m.content.clone(),
+            content: ChatContent::from_message(m),
+            tool_calls: ChatToolCall::from_message(m),
+            tool_call_id: m.tool_call_id.clone(),
         })
         .collect();
     
     let request = OpenAIRequest {
         model: model.to_string(),
         messages: openai_messages,
-        tools: None, // LM Studio doesn't support web search
+        tools: None,
+        stream: false,
+        stream_options: None,
+        sampling: ChatSampling::new(generation),
     };
     
     let api_url = format!("{}/v1/chat/completions", url.trim_end_matches('/'));
     
-    let response = client
-        .post(&api_url)
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to LM Studio at {}: {}", api_url, e))?;
+    let response = send_with_retry(
+        apply_http_options(client.post(&api_url), http)
+            .header("Content-Type", "application/json")
+            .json(&request),
+        None,
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to LM Studio at {}: {}", api_url, e)))?;
     
-    let status = response.status();
-    if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("LM Studio API error ({}): {}", status, error_text));
+    if !response.status().is_success() {
+        return Err(error_response(response, "LM Studio").await);
     }
     
-    let lmstudio_response: OpenAIResponse = response
+    let json: serde_json::Value = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse LM Studio response: {}", e))?;
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse LM Studio response: {}", e)))?;
+    let reasoning = json.pointer("/choices/0/message").and_then(chat_reasoning).map(|r| r.to_string());
+    let lmstudio_response: OpenAIResponse = serde_json::from_value(json)
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse LM Studio response: {}", e)))?;
     
     let content = lmstudio_response
         .choices
         .first()
-        .and_then(|c| Some(c.message.content.clone()))
-        .ok_or_else(|| "No response from LM Studio".to_string())?;
+        .and_then(|c| Some(c.message.content.text()))
+        .ok_or_else(|| AppError::invalid_response("No response from LM Studio"))?;
+    let (reasoning, content) = match reasoning {
+        Some(reasoning) => (reasoning, content),
+        None => split_think_tags(&content),
+    };
     
-    Ok(content)
+    Ok(Completion {
+        content,
+        reasoning,
+        usage: lmstudio_response.usage.map(TokenUsage::from),
+        ..Default::default()
+    })
 }
 
+// Stream LM Studio response (OpenAI-compatible SSE format), offering `tools` to the model
+pub async fn stream_lmstudio(client: &reqwest::Client, params: &LMStudioParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: &mut StreamSink) -> Result<(), AppError> {
+    let openai_messages: Vec<OpenAIMessage> = messages
+        .iter()
+        .map(|m| OpenAIMessage {
+            role: m.role.clone(),
+            content: ChatContent::from_message(m),
+            tool_calls: ChatToolCall::from_message(m),
+            tool_call_id: m.tool_call_id.clone(),
+        })
+        .collect();
+    
+    let request = OpenAIRequest {
+        model: params.model.clone(),
+        messages: openai_messages,
+        tools: chat_tools(tools),
+        stream: true,
+        stream_options: stream_options(true),
+        sampling: ChatSampling::new(generation),
+    };
+    
+    let api_url = format!("{}/v1/chat/completions", params.url.trim_end_matches('/'));
+    
+    let response = send_with_retry(
+        apply_http_options(client.post(&api_url), &params.http)
+            .header("Content-Type", "application/json")
+            .json(&request),
+        Some(sink),
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to LM Studio at {}: {}", api_url, e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "LM Studio").await);
+    }
+    
+    read_sse(response, sink, handle_chat_completion_event).await
+}
+
+// List models available in LM Studio. The native /api/v0/models endpoint
+// reports model type, context length and capabilities; older versions only
+// serve the OpenAI-compatible /v1/models.
+pub async fn list_lmstudio_models(client: &reqwest::Client, url: &str, http: &HttpOptions) -> Result<Vec<ModelInfo>, AppError> {
+    let base_url = url.trim_end_matches('/');
+    
+    let native_url = format!("{}/api/v0/models", base_url);
+    if let Ok(models) = fetch_model_list("LM Studio", apply_http_options(client.get(&native_url), http), &native_url).await {
+        return Ok(models);
+    }
+    
+    let api_url = format!("{}/v1/models", base_url);
+    fetch_model_list("LM Studio", apply_http_options(client.get(&api_url), http), &api_url).await
+}
+
+pub struct LMStudioProvider;
+
+#[async_trait]
+impl LlmProvider for LMStudioProvider {
+    fn id(&self) -> &'static str {
+        "lmstudio"
+    }
+    
+    fn display_name(&self) -> &'static str {
+        "LM Studio"
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn model(&self, config: &ProviderConfig) -> String {
+        config.params.lmstudio.model.clone()
+    }
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        config.params.lmstudio.model = model.to_string();
+    }
+    
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        if config.params.lmstudio.url.trim().is_empty() {
+            return Err(AppError::config("LM Studio URL is required"));
+        }
+        Ok(())
+    }
+    
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let generation = config.generation(&config.params.lmstudio.generation);
+        call_lmstudio(&config.client, &config.params.lmstudio.url, &config.params.lmstudio.model, &config.params.lmstudio.http, messages, &generation).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let generation = config.generation(&config.params.lmstudio.generation);
+        stream_lmstudio(&config.client, &config.params.lmstudio, messages, &generation, &config.tools, sink).await
+    }
+    
+    async fn list_models(&self, config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        list_lmstudio_models(&config.client, &config.params.lmstudio.url, &config.params.lmstudio.http).await
+    }
+}
@@ -1,7 +1,17 @@
 pub mod providers;
+pub mod catalog;
+pub mod pricing;
+pub mod context;
+pub mod http;
+pub mod stream;
+pub mod decode;
+pub mod retry;
 pub mod ollama;
 pub mod openai;
+pub mod openai_compatible;
+pub mod anthropic;
 pub mod google;
 pub mod lmstudio;
 pub mod custom;
-
+pub mod tools;
+pub mod web_search;
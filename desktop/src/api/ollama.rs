@@ -1,164 +1,383 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use async_trait::async_trait;
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter};
-use crate::models::Message;
+use crate::models::{AppError, ErrorKind, GenerationParams, HttpOptions, Message, ModelInfo, OllamaParams, ThinkingEffort, TokenUsage, ToolCall};
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::stream::{read_ndjson, StreamControl, StreamSink};
+use crate::api::decode::split_think_tags;
+use crate::api::openai::chat_tools;
+use crate::api::tools::ToolSpec;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct OllamaMessage {
     role: String,
     content: String,
+    // Reasoning of thinking models, returned apart from the content when `think` is set
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    thinking: String,
+    // Base64 images, for vision models
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OllamaToolCall>,
+    // Tool a "tool" message answers
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    tool_name: String,
+}
+
+// Ollama sends tool calls whole, with the arguments as an object and no id
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaToolCall {
+    function: OllamaFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OllamaFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+impl OllamaMessage {
+    fn from_message(message: &Message) -> Self {
+        OllamaMessage {
+            role: message.role.clone(),
+            content: message.content.clone(),
+            thinking: message.reasoning.clone(),
+            images: message.images.iter().map(|image| image.data.clone()).collect(),
+            tool_calls: message
+                .tool_calls
+                .iter()
+                .map(|call| OllamaToolCall {
+                    function: OllamaFunctionCall {
+                        name: call.name.clone(),
+                        arguments: call.arguments.clone(),
+                    },
+                })
+                .collect(),
+            tool_name: message.tool_name.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 struct OllamaRequest {
     model: String,
     messages: Vec<OllamaMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    think: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    options: serde_json::Map<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
 struct OllamaResponse {
     message: OllamaMessage,
-    #[serde(default)]
-    #[allow(dead_code)]
-    done: bool,
 }
 
-// Call Ollama API
-pub async fn call_ollama(url: &str, model: &str, messages: &[Message]) -> Result<String, String> {
-    let client = reqwest::Client::new();
+// Token counts from the final response (Ollama reports no cached count; a
+// reused prompt prefix is simply left out of `prompt_eval_count`)
+fn usage_from(json: &serde_json::Value) -> Option<TokenUsage> {
+    let prompt_tokens = json.get("prompt_eval_count").and_then(|c| c.as_u64());
+    let completion_tokens = json.get("eval_count").and_then(|c| c.as_u64());
+    if prompt_tokens.is_none() && completion_tokens.is_none() {
+        return None;
+    }
     
+    Some(TokenUsage {
+        prompt_tokens: prompt_tokens.unwrap_or(0),
+        completion_tokens: completion_tokens.unwrap_or(0),
+        ..Default::default()
+    })
+}
+
+// Map generation parameters to Ollama `options`. Explicit fields take
+// precedence over the same keys in `ollama-options`.
+fn build_options(generation: &GenerationParams) -> serde_json::Map<String, serde_json::Value> {
+    let mut options = generation.ollama_options.clone();
+    
+    if let Some(temperature) = generation.temperature {
+        options.insert("temperature".to_string(), serde_json::json!(temperature));
+    }
+    if let Some(top_p) = generation.top_p {
+        options.insert("top_p".to_string(), serde_json::json!(top_p));
+    }
+    if let Some(max_tokens) = generation.max_tokens {
+        options.insert("num_predict".to_string(), serde_json::json!(max_tokens));
+    }
+    if !generation.stop.is_empty() {
+        options.insert("stop".to_string(), serde_json::json!(generation.stop));
+    }
+    if let Some(seed) = generation.seed {
+        options.insert("seed".to_string(), serde_json::json!(seed));
+    }
+    
+    options
+}
+
+// The `think` request field: gpt-oss models take an effort level, other
+// thinking models only turn thinking on or off. Unset leaves the model's default.
+fn think_option(model: &str, generation: &GenerationParams) -> Option<serde_json::Value> {
+    match generation.thinking_effort? {
+        ThinkingEffort::Off => Some(serde_json::json!(false)),
+        effort if model.starts_with("gpt-oss") => Some(serde_json::json!(effort.as_str())),
+        _ => Some(serde_json::json!(true)),
+    }
+}
+
+// Error for a failed chat request, suggesting to pull a missing model
+async fn chat_error(response: reqwest::Response, model: &str) -> AppError {
+    let error = error_response(response, "Ollama").await;
+    if error.kind == ErrorKind::ModelNotFound {
+        return error.with_hint(format!("Run `ollama pull {}` or choose another model in Settings", model));
+    }
+    error
+}
+
+// Call Ollama API
+pub async fn call_ollama(client: &reqwest::Client, url: &str, model: &str, http: &HttpOptions, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
     // Convert messages to Ollama format
-    let ollama_messages: Vec<OllamaMessage> = messages
-        .iter()
-        .map(|m| OllamaMessage {
-            role: m.role.clone(),
-            content: m.content.clone(),
-        })
-        .collect();
+    let ollama_messages: Vec<OllamaMessage> = messages.iter().map(OllamaMessage::from_message).collect();
     
     let request = OllamaRequest {
         model: model.to_string(),
         messages: ollama_messages,
+        tools: None,
         stream: false,
+        think: think_option(model, generation),
+        options: build_options(generation),
     };
     
     let api_url = format!("{}/api/chat", url.trim_end_matches('/'));
     
-    let response = client
-        .post(&api_url)
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to Ollama at {}: {}", api_url, e))?;
+    let response = send_with_retry(
+        apply_http_options(client.post(&api_url), http)
+            .json(&request),
+        None,
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to Ollama at {}: {}", api_url, e)))?;
     
-    let status = response.status();
-    if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Ollama API error ({}): {}", status, error_text));
+    if !response.status().is_success() {
+        return Err(chat_error(response, model).await);
     }
     
-    let ollama_response: OllamaResponse = response
+    let json: serde_json::Value = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Ollama response: {}", e)))?;
+    let usage = usage_from(&json);
+    let ollama_response: OllamaResponse = serde_json::from_value(json)
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Ollama response: {}", e)))?;
+    
+    // Models without thinking support in Ollama write it inline in <think> tags
+    let message = ollama_response.message;
+    let (reasoning, content) = if message.thinking.is_empty() {
+        split_think_tags(&message.content)
+    } else {
+        (message.thinking, message.content)
+    };
     
-    Ok(ollama_response.message.content)
+    Ok(Completion {
+        content,
+        reasoning,
+        usage,
+        ..Default::default()
+    })
 }
 
-// Stream Ollama response
-pub async fn stream_ollama(
-    app: &AppHandle,
-    event_name: &str,
-    url: &str,
-    model: &str,
-    messages: &[Message],
-    full_response: &mut String,
-    periodic_save: Option<Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>>,
-    cancel_flag: Arc<AtomicBool>,
-) -> Result<(), String> {
-    let client = reqwest::Client::new();
+// Stream Ollama response, offering `tools` to the model
+pub async fn stream_ollama(client: &reqwest::Client, params: &OllamaParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: &mut StreamSink) -> Result<(), AppError> {
+    let ollama_messages: Vec<OllamaMessage> = messages.iter().map(OllamaMessage::from_message).collect();
     
-    let ollama_messages: Vec<OllamaMessage> = messages
-        .iter()
-        .map(|m| OllamaMessage {
-            role: m.role.clone(),
-            content: m.content.clone(),
-        })
-        .collect();
-    
-    let request = serde_json::json!({
-        "model": model,
-        "messages": ollama_messages,
-        "stream": true
-    });
+    let request = OllamaRequest {
+        model: params.model.clone(),
+        messages: ollama_messages,
+        tools: chat_tools(tools),
+        stream: true,
+        think: think_option(&params.model, generation),
+        options: build_options(generation),
+    };
     
-    let api_url = format!("{}/api/chat", url.trim_end_matches('/'));
+    let api_url = format!("{}/api/chat", params.url.trim_end_matches('/'));
     
-    let response = client
-        .post(&api_url)
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to Ollama: {}", e))?;
+    let response = send_with_retry(
+        apply_http_options(client.post(&api_url), &params.http)
+            .json(&request),
+        Some(sink),
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to Ollama: {}", e)))?;
     
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Ollama API error: {}", error_text));
-    }
-    
-    // Stream the response
-    use futures_util::StreamExt;
-    use std::time::{Duration, Instant};
-    let mut stream = response.bytes_stream();
-    let mut buffer = String::new();
-    let mut last_save_time = Instant::now();
-    const SAVE_INTERVAL: Duration = Duration::from_secs(2); // Save every 2 seconds
-    
-    while let Some(item) = stream.next().await {
-        // Check if aborted
-        if cancel_flag.load(Ordering::Relaxed) {
-            break;
-        }
-        
-        let chunk = item.map_err(|e| format!("Stream error: {}", e))?;
-        buffer.push_str(&String::from_utf8_lossy(&chunk));
-        
-        // Process complete lines
-        while let Some(newline_pos) = buffer.find('\n') {
-            let line = buffer[..newline_pos].trim().to_string();
-            buffer = buffer[newline_pos + 1..].to_string();
-            
-            if line.is_empty() {
-                continue;
+        return Err(chat_error(response, &params.model).await);
+    }
+    
+    // Stream the response (one JSON object per line)
+    read_ndjson(response, sink, |sink, line| {
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(line) {
+            if let Some(thinking) = json.pointer("/message/thinking").and_then(|t| t.as_str()) {
+                sink.push_reasoning(thinking);
             }
-            
-            // Parse JSON line
-            if let Ok(json) = serde_json::from_str::<serde_json::Value>(&line) {
-                if let Some(content) = json.get("message").and_then(|m| m.get("content")).and_then(|c| c.as_str()) {
-                    full_response.push_str(content);
-                    app.emit(event_name, content).map_err(|e| format!("Failed to emit chunk: {}", e))?;
-                    
-                    // Periodic save (every 2 seconds)
-                    if let Some(ref save_callback) = periodic_save {
-                        if last_save_time.elapsed() >= SAVE_INTERVAL {
-                            if let Err(e) = save_callback(full_response) {
-                                eprintln!("Warning: Failed to save partial message: {}", e);
-                            }
-                            last_save_time = Instant::now();
-                        }
+            if let Some(content) = json.get("message").and_then(|m| m.get("content")).and_then(|c| c.as_str()) {
+                sink.push_tagged(content)?;
+            }
+            if let Some(calls) = json.pointer("/message/tool_calls") {
+                if let Ok(calls) = serde_json::from_value::<Vec<OllamaToolCall>>(calls.clone()) {
+                    for call in calls {
+                        sink.add_tool_call(ToolCall {
+                            name: call.function.name,
+                            arguments: call.function.arguments,
+                            ..Default::default()
+                        });
                     }
                 }
-                
-                // Check if done
-                if json.get("done").and_then(|d| d.as_bool()).unwrap_or(false) {
-                    break;
-                }
+            }
+            
+            // Check if done; the final chunk carries the token counts
+            if json.get("done").and_then(|d| d.as_bool()).unwrap_or(false) {
+                sink.usage = usage_from(&json);
+                return Ok(StreamControl::Done);
             }
         }
+        Ok(StreamControl::Continue)
+    }).await
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaModel {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaTagsResponse {
+    models: Vec<OllamaModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OllamaShowResponse {
+    // Reported by Ollama 0.6.4+ ("completion", "vision", "tools", ...)
+    #[serde(default)]
+    capabilities: Option<Vec<String>>,
+    #[serde(default)]
+    model_info: HashMap<String, serde_json::Value>,
+}
+
+// Fill in context length and capabilities from /api/show
+async fn show_ollama_model(client: &reqwest::Client, http: &HttpOptions, base_url: &str, name: String) -> ModelInfo {
+    let mut model = ModelInfo::new(&name);
+    
+    let response = apply_http_options(client.post(format!("{}/api/show", base_url)), http)
+        .json(&serde_json::json!({ "model": name }))
+        .send()
+        .await;
+    
+    // Capabilities stay unknown when the model can't be shown
+    let Ok(response) = response else {
+        return model;
+    };
+    if !response.status().is_success() {
+        return model;
+    }
+    let Ok(show) = response.json::<OllamaShowResponse>().await else {
+        return model;
+    };
+    
+    // The context length key is prefixed with the architecture ("llama.context_length")
+    model.context_length = show
+        .model_info
+        .iter()
+        .find(|(key, _)| key.ends_with(".context_length"))
+        .and_then(|(_, value)| value.as_u64());
+    if let Some(capabilities) = show.capabilities {
+        model.vision = Some(capabilities.iter().any(|c| c == "vision"));
+        model.tools = capabilities.iter().any(|c| c == "tools");
+    }
+    
+    model
+}
+
+// List models installed in the local Ollama instance
+pub async fn list_ollama_models(client: &reqwest::Client, url: &str, http: &HttpOptions) -> Result<Vec<ModelInfo>, AppError> {
+    let base_url = url.trim_end_matches('/');
+    let api_url = format!("{}/api/tags", base_url);
+    
+    let response = apply_http_options(client.get(&api_url), http)
+        .send()
+        .await
+        .map_err(|e| request_error(&e, format!("Failed to connect to Ollama at {}: {}", api_url, e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Ollama").await);
     }
     
-    Ok(())
+    let tags_response: OllamaTagsResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse Ollama response: {}", e)))?;
+    
+    let models = tags_response.models
+        .into_iter()
+        .map(|m| show_ollama_model(client, http, base_url, m.name));
+    
+    Ok(join_all(models).await)
 }
 
+pub struct OllamaProvider;
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    fn id(&self) -> &'static str {
+        "ollama"
+    }
+    
+    fn display_name(&self) -> &'static str {
+        "Ollama"
+    }
+    
+    fn reports_vision(&self) -> bool {
+        true
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn model(&self, config: &ProviderConfig) -> String {
+        config.params.ollama.model.clone()
+    }
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        config.params.ollama.model = model.to_string();
+    }
+    
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        if config.params.ollama.url.trim().is_empty() {
+            return Err(AppError::config("Ollama URL is required"));
+        }
+        if config.params.ollama.model.trim().is_empty() {
+            return Err(AppError::config("Ollama model is required"));
+        }
+        Ok(())
+    }
+    
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let generation = config.generation(&config.params.ollama.generation);
+        call_ollama(&config.client, &config.params.ollama.url, &config.params.ollama.model, &config.params.ollama.http, messages, &generation).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let generation = config.generation(&config.params.ollama.generation);
+        stream_ollama(&config.client, &config.params.ollama, messages, &generation, &config.tools, sink).await
+    }
+    
+    async fn list_models(&self, config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        list_ollama_models(&config.client, &config.params.ollama.url, &config.params.ollama.http).await
+    }
+}
@@ -1,227 +1,779 @@
-use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Emitter};
-use crate::models::Message;
+use crate::models::{AppError, Citation, ErrorKind, GenerationParams, HttpOptions, Message, ModelInfo, OpenAIParams, ThinkingEffort, TokenUsage};
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::tools::ToolSpec;
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::decode::SseEvent;
+use crate::api::stream::{read_sse, StreamControl, StreamSink};
 
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+// Message content in Chat Completions format: plain text, or text and image
+// parts when the message carries images. Shared with the OpenAI-compatible
+// clients.
 #[derive(Debug, Serialize, Deserialize)]
-struct OpenAIMessage {
-    role: String,
-    content: String,
+#[serde(untagged)]
+pub enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatContentPart>),
 }
 
-#[derive(Debug, Serialize)]
-struct OpenAITool {
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChatContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ChatImageUrl },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatImageUrl {
+    url: String,
+}
+
+impl ChatContent {
+    // Images are sent inline as data URLs
+    pub fn from_message(message: &Message) -> Self {
+        if message.images.is_empty() {
+            return ChatContent::Text(message.content.clone());
+        }
+        
+        let mut parts = Vec::new();
+        if !message.content.is_empty() {
+            parts.push(ChatContentPart::Text { text: message.content.clone() });
+        }
+        parts.extend(message.images.iter().map(|image| ChatContentPart::ImageUrl {
+            image_url: ChatImageUrl {
+                url: format!("data:{};base64,{}", image.mime_type, image.data),
+            },
+        }));
+        ChatContent::Parts(parts)
+    }
+    
+    // Text of a response message
+    pub fn text(&self) -> String {
+        match self {
+            ChatContent::Text(text) => text.clone(),
+            ChatContent::Parts(parts) => parts
+                .iter()
+                .filter_map(|part| match part {
+                    ChatContentPart::Text { text } => Some(text.as_str()),
+                    ChatContentPart::ImageUrl { .. } => None,
+                })
+                .collect(),
+        }
+    }
+}
+
+// A tool call of an assistant message in Chat Completions format, with the
+// arguments as a JSON string. Shared with the OpenAI-compatible clients.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatToolCall {
+    id: String,
     #[serde(rename = "type")]
-    tool_type: String,
+    call_type: String,
+    function: ChatFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatFunctionCall {
+    name: String,
+    arguments: String,
+}
+
+impl ChatToolCall {
+    pub fn from_message(message: &Message) -> Vec<Self> {
+        message
+            .tool_calls
+            .iter()
+            .map(|call| ChatToolCall {
+                id: call.id.clone(),
+                call_type: "function".to_string(),
+                function: ChatFunctionCall {
+                    name: call.name.clone(),
+                    arguments: call.arguments.to_string(),
+                },
+            })
+            .collect()
+    }
+}
+
+// Tool definitions in Chat Completions format; none when no tools are offered
+pub fn chat_tools(tools: &[ToolSpec]) -> Option<Vec<serde_json::Value>> {
+    if tools.is_empty() {
+        return None;
+    }
+    
+    Some(
+        tools
+            .iter()
+            .map(|tool| {
+                serde_json::json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool.name,
+                        "description": tool.description,
+                        "parameters": tool.parameters,
+                    }
+                })
+            })
+            .collect(),
+    )
+}
+
+// Generation parameters in Chat Completions format, shared with the
+// OpenAI-compatible clients
+#[derive(Debug, Serialize, Default)]
+pub struct ChatSampling {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<&'static str>,
+}
+
+impl ChatSampling {
+    pub fn new(generation: &GenerationParams) -> Self {
+        Self {
+            temperature: generation.temperature,
+            top_p: generation.top_p,
+            max_tokens: generation.max_tokens,
+            stop: generation.stop.clone(),
+            seed: generation.seed,
+            // Servers differ in how (and whether) thinking is turned off, so
+            // only the levels every server taking `reasoning_effort` knows are sent
+            reasoning_effort: generation
+                .thinking_effort
+                .filter(|effort| *effort != ThinkingEffort::Off)
+                .map(|effort| effort.as_str()),
+        }
+    }
+}
+
+// `usage` block of a chat completion. Streams send it in a final chunk when
+// the request sets `stream_options.include_usage`.
+#[derive(Debug, Deserialize)]
+pub struct ChatUsage {
+    #[serde(default)]
+    prompt_tokens: u64,
+    #[serde(default)]
+    completion_tokens: u64,
+    #[serde(default)]
+    prompt_tokens_details: Option<ChatPromptTokensDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatPromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u64,
+}
+
+impl From<ChatUsage> for TokenUsage {
+    fn from(usage: ChatUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cached_tokens: usage.prompt_tokens_details.map(|d| d.cached_tokens).unwrap_or(0),
+            cost: None,
+        }
+    }
+}
+
+// Ask for the usage chunk at the end of a stream
+pub fn stream_options(stream: bool) -> Option<serde_json::Value> {
+    if stream {
+        Some(serde_json::json!({ "include_usage": true }))
+    } else {
+        None
+    }
+}
+
+// Reasoning of a chat completion message or delta. There is no standard
+// field: llama.cpp, vLLM, DeepSeek and LM Studio use `reasoning_content`,
+// OpenRouter and newer vLLM `reasoning`.
+pub fn chat_reasoning(message: &serde_json::Value) -> Option<&str> {
+    message
+        .get("reasoning_content")
+        .or_else(|| message.get("reasoning"))
+        .and_then(|r| r.as_str())
+}
+
+// Handle one SSE event of an OpenAI-style chat completion stream. Reasoning
+// comes in its own delta field or inline in <think> tags, depending on the
+// server.
+pub fn handle_chat_completion_event(sink: &mut StreamSink, event: &SseEvent) -> Result<StreamControl, AppError> {
+    let data = event.data.as_str();
+    if data == "[DONE]" {
+        return Ok(StreamControl::Done);
+    }
+    
+    if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
+        if let Some(reasoning) = json.pointer("/choices/0/delta").and_then(chat_reasoning) {
+            sink.push_reasoning(reasoning);
+        }
+        if let Some(content) = json.pointer("/choices/0/delta/content").and_then(|c| c.as_str()) {
+            sink.push_tagged(content)?;
+        }
+        if let Some(calls) = json.pointer("/choices/0/delta/tool_calls").and_then(|c| c.as_array()) {
+            handle_tool_call_deltas(sink, calls);
+        }
+        if let Some(usage) = json.get("usage").filter(|u| !u.is_null()) {
+            if let Ok(usage) = serde_json::from_value::<ChatUsage>(usage.clone()) {
+                sink.usage = Some(usage.into());
+            }
+        }
+    }
+    
+    Ok(StreamControl::Continue)
 }
 
+// Tool calls arrive as deltas: the first one of a call has its id and name,
+// the following ones fragments of its arguments for the call at `index`
+fn handle_tool_call_deltas(sink: &mut StreamSink, calls: &[serde_json::Value]) {
+    for call in calls {
+        let mut index = call.get("index").and_then(|i| i.as_u64()).map(|i| i as usize);
+        if let Some(name) = call.pointer("/function/name").and_then(|n| n.as_str()) {
+            let id = call.get("id").and_then(|i| i.as_str()).unwrap_or("");
+            sink.start_tool_call(id, name);
+            // Some servers send every call whole with the same index
+            index = None;
+        }
+        if let Some(arguments) = call.pointer("/function/arguments").and_then(|a| a.as_str()) {
+            sink.push_tool_arguments(index, arguments);
+        }
+    }
+}
+
+// Responses API request. Nothing is stored on OpenAI's side (`store: false`),
+// so the whole history is sent as `input` every time. `stop` and `seed` have
+// no equivalent in this API and are not sent.
 #[derive(Debug, Serialize)]
-struct OpenAIRequest {
+struct ResponsesRequest {
     model: String,
-    messages: Vec<OpenAIMessage>,
+    input: Vec<ResponsesInputItem>,
+    stream: bool,
+    store: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    tools: Option<Vec<OpenAITool>>,
+    top_p: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reasoning: Option<serde_json::Value>,
+}
+
+// An item of the input: a message, a function call the model made, or the
+// result of one
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesInputItem {
+    Message { role: String, content: ResponsesContent },
+    FunctionCall { call_id: String, name: String, arguments: String },
+    FunctionCallOutput { call_id: String, output: String },
+}
+
+// Message content: plain text, or text and image parts when the message
+// carries images
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ResponsesContent {
+    Text(String),
+    Parts(Vec<ResponsesContentPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesContentPart {
+    InputText { text: String },
+    InputImage { image_url: String },
+}
+
+impl ResponsesContent {
+    // Images are sent inline as data URLs
+    fn from_message(message: &Message) -> Self {
+        if message.images.is_empty() {
+            return ResponsesContent::Text(message.content.clone());
+        }
+        
+        let mut parts = Vec::new();
+        if !message.content.is_empty() {
+            parts.push(ResponsesContentPart::InputText { text: message.content.clone() });
+        }
+        parts.extend(message.images.iter().map(|image| ResponsesContentPart::InputImage {
+            image_url: format!("data:{};base64,{}", image.mime_type, image.data),
+        }));
+        ResponsesContent::Parts(parts)
+    }
+}
+
+// Tool calls and results are separate items in the Responses API rather than
+// fields of the messages
+fn responses_input(messages: &[Message]) -> Vec<ResponsesInputItem> {
+    let mut items = Vec::new();
+    
+    for message in messages {
+        if message.role == "tool" {
+            items.push(ResponsesInputItem::FunctionCallOutput {
+                call_id: message.tool_call_id.clone(),
+                output: message.content.clone(),
+            });
+            continue;
+        }
+        
+        if !message.content.is_empty() || !message.images.is_empty() {
+            items.push(ResponsesInputItem::Message {
+                role: message.role.clone(),
+                content: ResponsesContent::from_message(message),
+            });
+        }
+        items.extend(message.tool_calls.iter().map(|call| ResponsesInputItem::FunctionCall {
+            call_id: call.id.clone(),
+            name: call.name.clone(),
+            arguments: call.arguments.to_string(),
+        }));
+    }
+    
+    items
+}
+
+// Built-in web search plus the function tools. Strict mode, the Responses
+// API default, would reject schemas with optional properties.
+fn responses_tools(params: &OpenAIParams, tools: &[ToolSpec]) -> Vec<serde_json::Value> {
+    let mut definitions = Vec::new();
+    if params.web_search_enabled {
+        definitions.push(serde_json::json!({ "type": "web_search" }));
+    }
+    definitions.extend(tools.iter().map(|tool| {
+        serde_json::json!({
+            "type": "function",
+            "name": tool.name,
+            "description": tool.description,
+            "parameters": tool.parameters,
+            "strict": false,
+        })
+    }));
+    definitions
 }
 
 #[derive(Debug, Deserialize)]
-struct OpenAIChoice {
-    message: OpenAIMessage,
+struct ResponsesResponse {
+    #[serde(default)]
+    output: Vec<ResponsesOutputItem>,
+    #[serde(default)]
+    usage: Option<ResponsesUsage>,
 }
 
+// Output items other than messages and reasoning (web searches, function
+// calls) carry no text
 #[derive(Debug, Deserialize)]
-struct OpenAIResponse {
-    choices: Vec<OpenAIChoice>,
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesOutputItem {
+    Message {
+        #[serde(default)]
+        content: Vec<ResponsesOutputContent>,
+    },
+    // Summaries of the model's reasoning, when requested
+    Reasoning {
+        #[serde(default)]
+        summary: Vec<ResponsesSummary>,
+    },
+    #[serde(other)]
+    Other,
 }
 
-// Call OpenAI API
-pub async fn call_openai(api_key: &str, web_search_enabled: &bool, messages: &[Message]) -> Result<String, String> {
-    if api_key.is_empty() {
-        return Err("OpenAI API key is required".to_string());
+#[derive(Debug, Deserialize)]
+struct ResponsesSummary {
+    #[serde(default)]
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesOutputContent {
+    OutputText {
+        text: String,
+        #[serde(default)]
+        annotations: Vec<ResponsesAnnotation>,
+    },
+    Refusal {
+        refusal: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+// Annotation on the answer text; web search adds one `url_citation` per source
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ResponsesAnnotation {
+    UrlCitation {
+        url: String,
+        #[serde(default)]
+        title: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+impl ResponsesAnnotation {
+    fn citation(self) -> Option<Citation> {
+        match self {
+            ResponsesAnnotation::UrlCitation { url, title } => Some(Citation { url, title }),
+            ResponsesAnnotation::Other => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponsesUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    input_tokens_details: Option<ChatPromptTokensDetails>,
+}
+
+impl From<ResponsesUsage> for TokenUsage {
+    fn from(usage: ResponsesUsage) -> Self {
+        TokenUsage {
+            prompt_tokens: usage.input_tokens,
+            completion_tokens: usage.output_tokens,
+            cached_tokens: usage.input_tokens_details.map(|d| d.cached_tokens).unwrap_or(0),
+            cost: None,
+        }
+    }
+}
+
+// Models the Responses API's web search tool works with
+pub fn supports_web_search(model: &str) -> bool {
+    const SEARCH_PREFIXES: &[&str] = &["gpt-4o", "gpt-4.1", "gpt-5", "o3", "o4"];
+    SEARCH_PREFIXES.iter().any(|prefix| model.starts_with(prefix))
+        && !model.starts_with("gpt-4.1-nano")
+        && !model.starts_with("o3-mini")
+        && !model.contains("-search-")
+}
+
+// Check the selected model against the Responses API and the web search toggle
+fn check_model(params: &OpenAIParams) -> Result<(), AppError> {
+    if params.model.contains("-search-") {
+        return Err(AppError::config(format!(
+            "Model {} only works with Chat Completions. Choose a model such as gpt-4.1 and turn on web search instead.",
+            params.model
+        )));
+    }
+    if params.web_search_enabled && !supports_web_search(&params.model) {
+        return Err(AppError::config(format!(
+            "Model {} does not support web search. Choose a model such as gpt-4.1 or gpt-4o, or turn off web search.",
+            params.model
+        )));
+    }
+    Ok(())
+}
+
+// o-series and gpt-5 models take `reasoning`; other models reject it. The
+// gpt-5 chat models don't reason.
+fn is_reasoning_model(model: &str) -> bool {
+    let o_series = model.starts_with('o') && model[1..].starts_with(|c: char| c.is_ascii_digit());
+    o_series || (model.starts_with("gpt-5") && !model.contains("-chat"))
+}
+
+// Reasoning options for the thinking effort, asking for a summary of the
+// reasoning since OpenAI doesn't return the reasoning itself. gpt-5.1 and
+// later turn reasoning off with "none"; earlier models only go down to "minimal".
+fn reasoning_options(model: &str, generation: &GenerationParams) -> Option<serde_json::Value> {
+    if !is_reasoning_model(model) {
+        return None;
     }
     
-    let client = reqwest::Client::new();
+    match generation.thinking_effort? {
+        ThinkingEffort::Off if model.starts_with("gpt-5.") => Some(serde_json::json!({ "effort": "none" })),
+        ThinkingEffort::Off => Some(serde_json::json!({ "effort": "minimal" })),
+        effort => Some(serde_json::json!({ "effort": effort.as_str(), "summary": "auto" })),
+    }
+}
+
+// Reasoning models reject the sampling parameters, so they are left out
+fn build_request(params: &OpenAIParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], stream: bool) -> ResponsesRequest {
+    let sampling = |value: Option<f64>| value.filter(|_| !is_reasoning_model(&params.model));
     
-    // Convert messages to OpenAI format
-    let openai_messages: Vec<OpenAIMessage> = messages
-        .iter()
-        .map(|m| OpenAIMessage {
-            role: m.role.clone(),
-            content: m.content.clone(),
-        })
-        .collect();
+    ResponsesRequest {
+        model: params.model.clone(),
+        input: responses_input(messages),
+        stream,
+        store: false,
+        tools: responses_tools(params, tools),
+        temperature: sampling(generation.temperature),
+        top_p: sampling(generation.top_p),
+        max_output_tokens: generation.max_tokens,
+        reasoning: reasoning_options(&params.model, generation),
+    }
+}
+
+async fn send_request(client: &reqwest::Client, params: &OpenAIParams, request: &ResponsesRequest, sink: Option<&StreamSink>) -> Result<reqwest::Response, AppError> {
+    if params.api_key.is_empty() {
+        return Err(AppError::auth("OpenAI API key is required"));
+    }
+    check_model(params)?;
     
-    // Use gpt-4o model (supports web search tool)
-    let model = if *web_search_enabled {
-        "gpt-4o".to_string()  // gpt-4o supports web_search_preview tool
-    } else {
-        "gpt-4o-mini".to_string()
-    };
+    let url = format!("{}/responses", OPENAI_API_BASE);
     
-    // Include web_search_preview tool if enabled (note: tool type is web_search_preview, not web_search)
-    let tools = if *web_search_enabled {
-        Some(vec![OpenAITool {
-            tool_type: "web_search_preview".to_string(),
-        }])
-    } else {
-        None
+    let response = send_with_retry(
+        apply_http_options(client.post(&url), &params.http)
+            .header("Authorization", format!("Bearer {}", params.api_key))
+            .header("Content-Type", "application/json")
+            .json(request),
+        sink,
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to OpenAI: {}", e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "OpenAI").await);
+    }
+    
+    Ok(response)
+}
+
+// Call OpenAI API
+pub async fn call_openai(client: &reqwest::Client, params: &OpenAIParams, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
+    let request = build_request(params, messages, generation, &[], false);
+    let response = send_request(client, params, &request, None).await?;
+    
+    let openai_response: ResponsesResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse OpenAI response: {}", e)))?;
+    
+    let mut completion = Completion {
+        usage: openai_response.usage.map(TokenUsage::from),
+        ..Default::default()
     };
     
-    let request = OpenAIRequest {
-        model,
-        messages: openai_messages,
-        tools,
+    let mut contents = Vec::new();
+    for item in openai_response.output {
+        match item {
+            ResponsesOutputItem::Message { content } => contents.extend(content),
+            ResponsesOutputItem::Reasoning { summary } => {
+                for part in summary {
+                    if !completion.reasoning.is_empty() {
+                        completion.reasoning.push_str("\n\n");
+                    }
+                    completion.reasoning.push_str(&part.text);
+                }
+            }
+            ResponsesOutputItem::Other => {}
+        }
+    }
+    for content in contents {
+        match content {
+            ResponsesOutputContent::OutputText { text, annotations } => {
+                completion.content.push_str(&text);
+                for citation in annotations.into_iter().filter_map(ResponsesAnnotation::citation) {
+                    if !completion.citations.iter().any(|c| c.url == citation.url) {
+                        completion.citations.push(citation);
+                    }
+                }
+            }
+            ResponsesOutputContent::Refusal { refusal } => completion.content.push_str(&refusal),
+            ResponsesOutputContent::Other => {}
+        }
+    }
+    
+    if completion.content.is_empty() {
+        return Err(AppError::invalid_response("No response from OpenAI"));
+    }
+    Ok(completion)
+}
+
+// Stream OpenAI response, offering `tools` to the model
+pub async fn stream_openai(client: &reqwest::Client, params: &OpenAIParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: &mut StreamSink) -> Result<(), AppError> {
+    let request = build_request(params, messages, generation, tools, true);
+    let response = send_request(client, params, &request, Some(sink)).await?;
+    
+    read_sse(response, sink, handle_responses_event).await
+}
+
+// Handle one SSE event of a Responses API stream. Text arrives as
+// `response.output_text.delta`, reasoning summaries as
+// `response.reasoning_summary_text.delta`, web search sources as annotations,
+// function calls as an output item followed by argument deltas, and usage
+// with `response.completed`.
+fn handle_responses_event(sink: &mut StreamSink, event: &SseEvent) -> Result<StreamControl, AppError> {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(&event.data) else {
+        return Ok(StreamControl::Continue);
     };
+    let text = |pointer: &str| json.pointer(pointer).and_then(|v| v.as_str()).unwrap_or_default();
     
-    let url = "https://api.openai.com/v1/chat/completions";
+    match text("/type") {
+        "response.output_text.delta" | "response.refusal.delta" => sink.push(text("/delta"))?,
+        "response.reasoning_summary_text.delta" => sink.push_reasoning(text("/delta")),
+        // Summaries come in parts, each a paragraph or so
+        "response.reasoning_summary_part.added" if !sink.reasoning.is_empty() => sink.push_reasoning("\n\n"),
+        "response.output_text.annotation.added" => {
+            if let Some(annotation) = json.get("annotation") {
+                if let Ok(annotation) = serde_json::from_value::<ResponsesAnnotation>(annotation.clone()) {
+                    sink.add_citations(annotation.citation().into_iter().collect());
+                }
+            }
+        }
+        "response.output_item.added" if text("/item/type") == "function_call" => {
+            sink.start_tool_call(text("/item/call_id"), text("/item/name"));
+        }
+        "response.function_call_arguments.delta" => sink.push_tool_arguments(None, text("/delta")),
+        "response.completed" | "response.incomplete" => {
+            if let Some(usage) = json.pointer("/response/usage").filter(|u| !u.is_null()) {
+                if let Ok(usage) = serde_json::from_value::<ResponsesUsage>(usage.clone()) {
+                    sink.usage = Some(usage.into());
+                }
+            }
+            return Ok(StreamControl::Done);
+        }
+        "response.failed" => {
+            return Err(AppError::new(
+                ErrorKind::Server,
+                format!("OpenAI response failed: {}", text("/response/error/message")),
+            ));
+        }
+        "error" => {
+            return Err(AppError::new(ErrorKind::Server, format!("OpenAI stream error: {}", text("/message"))));
+        }
+        _ => {}
+    }
     
-    let response = client
-        .post(url)
+    Ok(StreamControl::Continue)
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModel {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAIModelsResponse {
+    data: Vec<OpenAIModel>,
+}
+
+// /v1/models also returns embedding, audio, image and moderation models
+fn is_chat_model(id: &str) -> bool {
+    const CHAT_PREFIXES: &[&str] = &["gpt-", "chatgpt-", "o1", "o3", "o4"];
+    // The search preview models only work with Chat Completions
+    const NON_CHAT_MARKERS: &[&str] = &[
+        "instruct", "audio", "realtime", "transcribe", "tts", "image", "embedding", "moderation", "-search-",
+    ];
+    
+    CHAT_PREFIXES.iter().any(|prefix| id.starts_with(prefix))
+        && !NON_CHAT_MARKERS.iter().any(|marker| id.contains(marker))
+}
+
+// /v1/models only returns ids, so capabilities come from the model family
+fn openai_model_info(id: &str) -> ModelInfo {
+    const VISION_PREFIXES: &[&str] = &["gpt-4o", "gpt-4.1", "gpt-4-turbo", "gpt-5", "chatgpt-4o", "o1", "o3", "o4"];
+    
+    let mut model = ModelInfo::new(id);
+    model.vision = Some(
+        VISION_PREFIXES.iter().any(|prefix| id.starts_with(prefix))
+            && !id.starts_with("o1-mini")
+            && !id.starts_with("o3-mini"),
+    );
+    // The ChatGPT snapshots don't accept function tools
+    model.tools = !id.starts_with("chatgpt-");
+    model
+}
+
+// List chat-capable models available to the API key
+pub async fn list_openai_models(client: &reqwest::Client, api_key: &str, http: &HttpOptions) -> Result<Vec<ModelInfo>, AppError> {
+    if api_key.is_empty() {
+        return Err(AppError::auth("OpenAI API key is required"));
+    }
+    
+    let url = format!("{}/models", OPENAI_API_BASE);
+    
+    let response = apply_http_options(client.get(&url), http)
         .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
         .send()
         .await
-        .map_err(|e| format!("Failed to connect to OpenAI: {}", e))?;
+        .map_err(|e| request_error(&e, format!("Failed to connect to OpenAI: {}", e)))?;
     
-    let status = response.status();
-    if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("OpenAI API error ({}): {}", status, error_text));
+    if !response.status().is_success() {
+        return Err(error_response(response, "OpenAI").await);
     }
     
-    let openai_response: OpenAIResponse = response
+    let models_response: OpenAIModelsResponse = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse OpenAI response: {}", e))?;
-    
-    let content = openai_response
-        .choices
-        .first()
-        .and_then(|c| Some(c.message.content.clone()))
-        .ok_or_else(|| "No response from OpenAI".to_string())?;
-    
-    Ok(content)
-}
-
-// Stream OpenAI response
-pub async fn stream_openai(
-    app: &AppHandle,
-    event_name: &str,
-    api_key: &str,
-    web_search_enabled: &bool,
-    messages: &[Message],
-    full_response: &mut String,
-    periodic_save: Option<Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>>,
-    cancel_flag: Arc<AtomicBool>,
-) -> Result<(), String> {
-    if api_key.is_empty() {
-        return Err("OpenAI API key is required".to_string());
-    }
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse OpenAI response: {}", e)))?;
     
-    let client = reqwest::Client::new();
-    
-    let openai_messages: Vec<OpenAIMessage> = messages
-        .iter()
-        .map(|m| OpenAIMessage {
-            role: m.role.clone(),
-            content: m.content.clone(),
-        })
+    let mut ids: Vec<String> = models_response.data
+        .into_iter()
+        .map(|m| m.id)
+        .filter(|id| is_chat_model(id))
         .collect();
+    ids.sort();
     
-    // Use gpt-4o model (supports web search tool)
-    let model = if *web_search_enabled {
-        "gpt-4o"
-    } else {
-        "gpt-4o-mini"
-    };
+    Ok(ids.iter().map(|id| openai_model_info(id)).collect())
+}
+
+pub struct OpenAIProvider;
+
+#[async_trait]
+impl LlmProvider for OpenAIProvider {
+    fn id(&self) -> &'static str {
+        "openai"
+    }
     
-    // Build request with optional tools
-    let mut request = serde_json::json!({
-        "model": model,
-        "messages": openai_messages,
-        "stream": true
-    });
-    
-    // Add web_search_preview tool if enabled (note: tool type is web_search_preview)
-    if *web_search_enabled {
-        request["tools"] = serde_json::json!([
-            {
-                "type": "web_search_preview"
-            }
-        ]);
+    fn display_name(&self) -> &'static str {
+        "OpenAI"
     }
     
-    let url = "https://api.openai.com/v1/chat/completions";
+    fn reports_vision(&self) -> bool {
+        true
+    }
     
-    let response = client
-        .post(url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .header("Content-Type", "application/json")
-        .json(&request)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to OpenAI: {}", e))?;
+    fn supports_tools(&self) -> bool {
+        true
+    }
     
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("OpenAI API error: {}", error_text));
+    fn model(&self, config: &ProviderConfig) -> String {
+        config.params.openai.model.clone()
     }
     
-    // Stream the response (SSE format)
-    use futures_util::StreamExt;
-    use std::time::{Duration, Instant};
-    let mut stream = response.bytes_stream();
-    let mut buffer = String::new();
-    let mut last_save_time = Instant::now();
-    const SAVE_INTERVAL: Duration = Duration::from_secs(2); // Save every 2 seconds
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        config.params.openai.model = model.to_string();
+    }
     
-    while let Some(item) = stream.next().await {
-        // Check if aborted
-        if cancel_flag.load(Ordering::Relaxed) {
-            break;
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        if config.params.openai.api_key.is_empty() {
+            return Err(AppError::auth("OpenAI API key is required"));
         }
-        
-        let chunk = item.map_err(|e| format!("Stream error: {}", e))?;
-        buffer.push_str(&String::from_utf8_lossy(&chunk));
-        
-        // Process SSE lines
-        while let Some(newline_pos) = buffer.find('\n') {
-            let line = buffer[..newline_pos].trim().to_string();
-            buffer = buffer[newline_pos + 1..].to_string();
-            
-            if line.starts_with("data: ") {
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    break;
-                }
-                
-                if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
-                    if let Some(choices) = json.get("choices").and_then(|c| c.as_array()) {
-                        if let Some(choice) = choices.first() {
-                            if let Some(delta) = choice.get("delta") {
-                                if let Some(content) = delta.get("content").and_then(|c| c.as_str()) {
-                                    full_response.push_str(content);
-                                    app.emit(event_name, content).map_err(|e| format!("Failed to emit chunk: {}", e))?;
-                                    
-                                    // Periodic save (every 2 seconds)
-                                    if let Some(ref save_callback) = periodic_save {
-                                        if last_save_time.elapsed() >= SAVE_INTERVAL {
-                                            if let Err(e) = save_callback(full_response) {
-                                                eprintln!("Warning: Failed to save partial message: {}", e);
-                                            }
-                                            last_save_time = Instant::now();
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+        if config.params.openai.model.trim().is_empty() {
+            return Err(AppError::config("OpenAI model is required"));
         }
+        check_model(&config.params.openai)
     }
     
-    Ok(())
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let generation = config.generation(&config.params.openai.generation);
+        call_openai(&config.client, &config.params.openai, messages, &generation).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let generation = config.generation(&config.params.openai.generation);
+        stream_openai(&config.client, &config.params.openai, messages, &generation, &config.tools, sink).await
+    }
+    
+    async fn list_models(&self, config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        list_openai_models(&config.client, &config.params.openai.api_key, &config.params.openai.http).await
+    }
 }
-
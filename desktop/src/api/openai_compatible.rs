@@ -0,0 +1,268 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::models::{AppError, GenerationParams, Message, ModelInfo, OpenAICompatibleParams, TokenUsage};
+use crate::api::providers::{Completion, LlmProvider, ProviderConfig};
+use crate::api::openai::{chat_reasoning, chat_tools, handle_chat_completion_event, stream_options, ChatContent, ChatSampling, ChatToolCall, ChatUsage};
+use crate::api::decode::split_think_tags;
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::api::retry::send_with_retry;
+use crate::api::stream::{read_sse, StreamSink};
+use crate::api::tools::ToolSpec;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: ChatContent,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ChatToolCall>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    tool_call_id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+    #[serde(flatten)]
+    sampling: ChatSampling,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+    #[serde(default)]
+    usage: Option<ChatUsage>,
+}
+
+// Add the API key (when set) and any extra headers and auth configured for the endpoint
+fn apply_headers(request: reqwest::RequestBuilder, params: &OpenAICompatibleParams) -> reqwest::RequestBuilder {
+    let mut request = request;
+    if !params.api_key.is_empty() {
+        request = request.header("Authorization", format!("Bearer {}", params.api_key));
+    }
+    apply_http_options(request, &params.http)
+}
+
+async fn send_chat_request(client: &reqwest::Client, name: &str, params: &OpenAICompatibleParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: Option<&StreamSink>) -> Result<reqwest::Response, AppError> {
+    let stream = sink.is_some(); // Only streamed requests report to a sink
+    let request = ChatRequest {
+        model: params.model.clone(),
+        messages: messages
+            .iter()
+            .map(|m| ChatMessage {
+                role: m.role.clone(),
+                content: ChatContent::from_message(m),
+                tool_calls: ChatToolCall::from_message(m),
+                tool_call_id: m.tool_call_id.clone(),
+            })
+            .collect(),
+        stream,
+        stream_options: stream_options(stream),
+        tools: chat_tools(tools),
+        sampling: ChatSampling::new(generation),
+    };
+    
+    let api_url = format!("{}/chat/completions", params.base_url.trim_end_matches('/'));
+    
+    let response = send_with_retry(
+        apply_headers(client.post(&api_url), params)
+            .header("Content-Type", "application/json")
+            .json(&request),
+        sink,
+    )
+    .await
+    .map_err(|e| request_error(&e, format!("Failed to connect to {} at {}: {}", name, api_url, e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, name).await);
+    }
+    
+    Ok(response)
+}
+
+// Call an OpenAI-compatible chat completions endpoint
+pub async fn call_openai_compatible(client: &reqwest::Client, name: &str, params: &OpenAICompatibleParams, messages: &[Message], generation: &GenerationParams) -> Result<Completion, AppError> {
+    let response = send_chat_request(client, name, params, messages, generation, &[], None).await?;
+    
+    let json: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse {} response: {}", name, e)))?;
+    let reasoning = json.pointer("/choices/0/message").and_then(chat_reasoning).map(|r| r.to_string());
+    let chat_response: ChatResponse = serde_json::from_value(json)
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse {} response: {}", name, e)))?;
+    
+    let content = chat_response
+        .choices
+        .into_iter()
+        .next()
+        .map(|c| c.message.content.text())
+        .ok_or_else(|| AppError::invalid_response(format!("No response from {}", name)))?;
+    let (reasoning, content) = match reasoning {
+        Some(reasoning) => (reasoning, content),
+        None => split_think_tags(&content),
+    };
+    
+    Ok(Completion {
+        content,
+        reasoning,
+        usage: chat_response.usage.map(TokenUsage::from),
+        ..Default::default()
+    })
+}
+
+// Stream an OpenAI-compatible chat completions endpoint (SSE format),
+// offering `tools` to the model
+pub async fn stream_openai_compatible(client: &reqwest::Client, name: &str, params: &OpenAICompatibleParams, messages: &[Message], generation: &GenerationParams, tools: &[ToolSpec], sink: &mut StreamSink) -> Result<(), AppError> {
+    let response = send_chat_request(client, name, params, messages, generation, tools, Some(sink)).await?;
+    read_sse(response, sink, handle_chat_completion_event).await
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelsResponse {
+    data: Vec<serde_json::Value>,
+}
+
+// Build a model entry from one item of an OpenAI-style model list. Only `id`
+// is standard; servers such as OpenRouter, Groq and LM Studio add names,
+// context sizes and capabilities under their own keys.
+pub fn parse_model_entry(entry: &serde_json::Value) -> Option<ModelInfo> {
+    let mut model = ModelInfo::new(entry.get("id")?.as_str()?);
+    
+    // LM Studio also lists embedding models
+    let model_type = entry.get("type").and_then(|t| t.as_str()).unwrap_or_default();
+    if model_type == "embeddings" {
+        return None;
+    }
+    
+    if let Some(name) = entry.get("name").and_then(|n| n.as_str()) {
+        model.display_name = name.to_string();
+    }
+    
+    model.context_length = ["context_length", "context_window", "max_context_length"]
+        .iter()
+        .find_map(|key| entry.get(*key).and_then(|v| v.as_u64()));
+    
+    let contains = |pointer: &str, needle: &str| {
+        entry
+            .pointer(pointer)
+            .and_then(|v| v.as_array())
+            .map(|items| items.iter().any(|item| item.as_str() == Some(needle)))
+            .unwrap_or(false)
+    };
+    
+    model.vision = if model_type == "vlm" || contains("/architecture/input_modalities", "image") {
+        Some(true)
+    } else if entry.pointer("/architecture/input_modalities").is_some() {
+        Some(false)
+    } else {
+        None
+    };
+    model.tools = contains("/supported_parameters", "tools") || contains("/capabilities", "tool_use");
+    
+    Some(model)
+}
+
+// Fetch an OpenAI-style model list from `api_url`
+pub async fn fetch_model_list(name: &str, request: reqwest::RequestBuilder, api_url: &str) -> Result<Vec<ModelInfo>, AppError> {
+    let response = request
+        .send()
+        .await
+        .map_err(|e| request_error(&e, format!("Failed to connect to {} at {}: {}", name, api_url, e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, name).await);
+    }
+    
+    let models_response: ModelsResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse {} response: {}", name, e)))?;
+    
+    Ok(models_response.data.iter().filter_map(parse_model_entry).collect())
+}
+
+// List models served by the endpoint (GET {base-url}/models)
+pub async fn list_openai_compatible_models(client: &reqwest::Client, name: &str, params: &OpenAICompatibleParams) -> Result<Vec<ModelInfo>, AppError> {
+    let api_url = format!("{}/models", params.base_url.trim_end_matches('/'));
+    
+    fetch_model_list(name, apply_headers(client.get(&api_url), params), &api_url).await
+}
+
+// Look up the named endpoint selected by the provider setting
+fn endpoint(config: &ProviderConfig) -> Result<(&str, &OpenAICompatibleParams), AppError> {
+    let name = config
+        .entry
+        .as_deref()
+        .ok_or_else(|| AppError::config("Select an OpenAI-compatible endpoint (provider \"openai-compatible:<name>\")"))?;
+    
+    config
+        .params
+        .openai_compatible
+        .get(name)
+        .map(|params| (name, params))
+        .ok_or_else(|| AppError::config(format!("Unknown OpenAI-compatible endpoint: {}", name)))
+}
+
+pub struct OpenAICompatibleProvider;
+
+#[async_trait]
+impl LlmProvider for OpenAICompatibleProvider {
+    fn id(&self) -> &'static str {
+        "openai-compatible"
+    }
+    
+    fn display_name(&self) -> &'static str {
+        "OpenAI-compatible"
+    }
+    
+    fn supports_tools(&self) -> bool {
+        true
+    }
+    
+    fn model(&self, config: &ProviderConfig) -> String {
+        endpoint(config).map(|(_, params)| params.model.clone()).unwrap_or_default()
+    }
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str) {
+        if let Some(params) = config.entry.as_ref().and_then(|name| config.params.openai_compatible.get_mut(name)) {
+            params.model = model.to_string();
+        }
+    }
+    
+    fn validate(&self, config: &ProviderConfig) -> Result<(), AppError> {
+        let (name, params) = endpoint(config)?;
+        if params.base_url.trim().is_empty() {
+            return Err(AppError::config(format!("Base URL is required for {}", name)));
+        }
+        if params.model.trim().is_empty() {
+            return Err(AppError::config(format!("Model is required for {}", name)));
+        }
+        Ok(())
+    }
+    
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError> {
+        let (name, params) = endpoint(config)?;
+        call_openai_compatible(&config.client, name, params, messages, &config.generation(&params.generation)).await
+    }
+    
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let (name, params) = endpoint(config)?;
+        stream_openai_compatible(&config.client, name, params, messages, &config.generation(&params.generation), &config.tools, sink).await
+    }
+    
+    async fn list_models(&self, config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        let (name, params) = endpoint(config)?;
+        list_openai_compatible_models(&config.client, name, params).await
+    }
+}
@@ -0,0 +1,58 @@
+use crate::models::TokenUsage;
+
+// USD per million tokens: (model prefix, input, cached input, output).
+// The longest matching prefix wins, so "gpt-4o-mini" is not priced as "gpt-4o".
+const PRICES: &[(&str, f64, f64, f64)] = &[
+    // OpenAI
+    ("gpt-4o", 2.50, 1.25, 10.00),
+    ("gpt-4o-mini", 0.15, 0.075, 0.60),
+    ("chatgpt-4o", 5.00, 5.00, 15.00),
+    ("gpt-4.1", 2.00, 0.50, 8.00),
+    ("gpt-4.1-mini", 0.40, 0.10, 1.60),
+    ("gpt-4.1-nano", 0.10, 0.025, 0.40),
+    ("gpt-5", 1.25, 0.125, 10.00),
+    ("gpt-5-mini", 0.25, 0.025, 2.00),
+    ("gpt-5-nano", 0.05, 0.005, 0.40),
+    ("o1", 15.00, 7.50, 60.00),
+    ("o1-mini", 1.10, 0.55, 4.40),
+    ("o3", 2.00, 0.50, 8.00),
+    ("o3-mini", 1.10, 0.55, 4.40),
+    ("o4-mini", 1.10, 0.275, 4.40),
+    // Anthropic (cache writes are priced as regular input)
+    ("claude-opus-4", 15.00, 1.50, 75.00),
+    ("claude-opus-4-5", 5.00, 0.50, 25.00),
+    ("claude-sonnet-4", 3.00, 0.30, 15.00),
+    ("claude-3-7-sonnet", 3.00, 0.30, 15.00),
+    ("claude-3-5-sonnet", 3.00, 0.30, 15.00),
+    ("claude-haiku-4-5", 1.00, 0.10, 5.00),
+    ("claude-3-5-haiku", 0.80, 0.08, 4.00),
+    // Google (prompts up to 200k tokens)
+    ("gemini-2.5-pro", 1.25, 0.125, 10.00),
+    ("gemini-2.5-flash", 0.30, 0.03, 2.50),
+    ("gemini-2.5-flash-lite", 0.10, 0.01, 0.40),
+    ("gemini-2.0-flash", 0.10, 0.025, 0.40),
+    ("gemini-2.0-flash-lite", 0.075, 0.075, 0.30),
+];
+
+// Estimate the cost of a response. Local providers are free; other providers
+// and models missing from the table have no estimate.
+pub fn estimate_cost(provider_id: &str, model: &str, usage: &TokenUsage) -> Option<f64> {
+    match provider_id {
+        "ollama" | "lmstudio" => return Some(0.0),
+        "openai" | "anthropic" | "google" => {}
+        _ => return None,
+    }
+    
+    let (_, input, cached_input, output) = PRICES
+        .iter()
+        .filter(|(prefix, ..)| model.starts_with(prefix))
+        .max_by_key(|(prefix, ..)| prefix.len())?;
+    
+    let cached = usage.cached_tokens.min(usage.prompt_tokens);
+    let uncached = usage.prompt_tokens - cached;
+    
+    Some(
+        (uncached as f64 * input + cached as f64 * cached_input + usage.completion_tokens as f64 * output)
+            / 1_000_000.0,
+    )
+}
@@ -1,102 +1,417 @@
-use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
-use tauri::AppHandle;
+use std::borrow::Cow;
+use async_trait::async_trait;
+use tauri::{AppHandle, Manager};
 use serde_json;
 
-use crate::models::Message;
+use crate::models::{AppError, Citation, ErrorKind, GenerationParams, Message, ModelInfo, ProviderParams, TokenUsage};
 use crate::storage::settings::load_settings;
-use crate::api::{ollama, openai, google, lmstudio, custom};
+use crate::api::stream::{StreamSink, StreamStatus};
+use crate::api::pricing::estimate_cost;
+use crate::api::http::HttpClient;
+use crate::api::catalog;
+use crate::api::tools::{flatten_tool_messages, ToolSpec};
+use crate::api::{ollama, openai, openai_compatible, anthropic, google, lmstudio, custom};
 
-// Call LLM - supports multiple providers
-pub async fn call_llm(app: &AppHandle, messages: &[Message]) -> Result<String, String> {
-    // Load settings to determine which service to use
-    let settings = load_settings(app)?;
+// Result of a one-shot completion
+#[derive(Debug, Default)]
+pub struct Completion {
+    pub content: String,
+    pub reasoning: String,
+    pub citations: Vec<Citation>,
+    pub usage: Option<TokenUsage>,
+    pub provider: String, // Filled in by `call_llm`
+    pub model: String,
+}
+
+// Settings for a provider call: every provider's parameters plus, for
+// providers with several named endpoints, the selected entry
+// (a provider setting of "openai-compatible:groq" selects entry "groq"),
+// generation parameters overriding the provider's defaults, the tools
+// offered to the model and the shared HTTP client
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    pub params: ProviderParams,
+    pub entry: Option<String>,
+    pub overrides: GenerationParams,
+    pub tools: Vec<ToolSpec>, // Empty unless the provider supports tools
+    pub client: reqwest::Client,
+}
+
+impl ProviderConfig {
+    // Effective generation parameters for a provider with the given defaults
+    pub fn generation(&self, defaults: &GenerationParams) -> GenerationParams {
+        defaults.merge(&self.overrides)
+    }
+}
+
+// Per-conversation choices layered over the settings: a persona's provider and
+// model, generation parameters overriding the provider defaults, and the
+// tools the model may call
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    pub provider: Option<String>, // Used instead of the "provider" setting
+    pub model: Option<String>,
+    pub generation: GenerationParams,
+    pub tools: Vec<ToolSpec>, // Only offered by `stream_llm`, which reports tool calls
+}
+
+// Common interface implemented by every LLM backend in `api/`
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    // Provider id as stored in the "provider" setting
+    fn id(&self) -> &'static str;
+    
+    fn display_name(&self) -> &'static str;
+    
+    // Configured model, and replacing it for a single call
+    fn model(&self, config: &ProviderConfig) -> String;
+    
+    fn set_model(&self, config: &mut ProviderConfig, model: &str);
+    
+    // Whether messages may carry images for this provider
+    fn supports_images(&self) -> bool {
+        true
+    }
+    
+    // Whether `list_models` reliably reports which models accept images, so
+    // images can be refused before they reach a text-only model
+    fn reports_vision(&self) -> bool {
+        false
+    }
+    
+    // Whether the provider can offer tools to the model and stream back its
+    // tool calls
+    fn supports_tools(&self) -> bool {
+        false
+    }
+    
+    // Check that the provider's settings are usable before making a request
+    fn validate(&self, _config: &ProviderConfig) -> Result<(), AppError> {
+        Ok(())
+    }
+    
+    // One-shot completion
+    async fn complete(&self, config: &ProviderConfig, messages: &[Message]) -> Result<Completion, AppError>;
     
-    let provider = settings
-        .get("provider")
-        .and_then(|v| v.as_str())
-        .unwrap_or("ollama")
-        .to_string();
+    // Streamed completion. Providers without native streaming deliver the
+    // whole completion as a single chunk.
+    async fn stream(&self, config: &ProviderConfig, messages: &[Message], sink: &mut StreamSink) -> Result<(), AppError> {
+        let completion = self.complete(config, messages).await?;
+        sink.add_citations(completion.citations);
+        sink.usage = completion.usage;
+        sink.push_reasoning(&completion.reasoning);
+        sink.push(&completion.content)
+    }
+    
+    // Models offered by the provider, for the model picker
+    async fn list_models(&self, _config: &ProviderConfig) -> Result<Vec<ModelInfo>, AppError> {
+        Err(AppError::config(format!("Model listing is not supported for {}", self.display_name())))
+    }
+}
+
+// Registry of available providers, keyed by `LlmProvider::id`
+static PROVIDERS: &[&dyn LlmProvider] = &[
+    &ollama::OllamaProvider,
+    &openai::OpenAIProvider,
+    &anthropic::AnthropicProvider,
+    &google::GoogleProvider,
+    &lmstudio::LMStudioProvider,
+    &custom::CustomProvider,
+    &openai_compatible::OpenAICompatibleProvider,
+];
+
+// Resolve a provider setting such as "ollama" or "openai-compatible:groq"
+// into the registered provider and the named entry, if any
+pub fn get_provider(id: &str) -> Result<(&'static dyn LlmProvider, Option<String>), AppError> {
+    let (provider_id, entry) = match id.split_once(':') {
+        Some((provider_id, entry)) => (provider_id, Some(entry.to_string())),
+        None => (id, None),
+    };
+    
+    let provider = PROVIDERS
+        .iter()
+        .copied()
+        .find(|p| p.id() == provider_id)
+        .ok_or_else(|| AppError::config(format!("Unknown provider: {}", id)))?;
+    
+    Ok((provider, entry))
+}
+
+// Load the provider parameters from settings for a provider setting such as
+// "ollama" or "openai-compatible:groq"
+pub fn load_provider_config(app: &AppHandle, provider: &str) -> Result<(&'static dyn LlmProvider, ProviderConfig), AppError> {
+    let settings = load_settings(app)?;
     
     let provider_params_value = settings
         .get("provider-params")
-        .ok_or_else(|| "Missing provider-params in settings".to_string())?;
+        .ok_or_else(|| AppError::config("Missing provider-params in settings"))?;
     
     // Parse provider_params as a JSON object
-    let provider_params: crate::models::ProviderParams = serde_json::from_value(provider_params_value.clone())
-        .map_err(|e| format!("Failed to parse provider-params: {}", e))?;
+    let provider_params: ProviderParams = serde_json::from_value(provider_params_value.clone())
+        .map_err(|e| AppError::config(format!("Failed to parse provider-params: {}", e)))?;
     
-    match provider.as_str() {
-        "ollama" => {
-            ollama::call_ollama(&provider_params.ollama.url, &provider_params.ollama.model, messages).await
-        }
-        "openai" => {
-            openai::call_openai(&provider_params.openai.api_key, &provider_params.openai.web_search_enabled, messages).await
+    let (provider, entry) = get_provider(provider)?;
+    let config = ProviderConfig {
+        params: provider_params,
+        entry,
+        overrides: GenerationParams::default(),
+        tools: Vec::new(),
+        client: app.state::<HttpClient>().get(),
+    };
+    
+    Ok((provider, config))
+}
+
+// Provider settings to try in order: the selected provider (or the one chosen
+// in `options`), then the "fallback-providers" setting
+fn provider_chain(app: &AppHandle, options: &CallOptions) -> Result<Vec<String>, AppError> {
+    let settings = load_settings(app)?;
+    
+    let primary = match &options.provider {
+        Some(provider) => provider.clone(),
+        None => settings
+            .get("provider")
+            .and_then(|v| v.as_str())
+            .unwrap_or("ollama")
+            .to_string(),
+    };
+    
+    let fallbacks: Vec<String> = match settings.get("fallback-providers") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse fallback-providers: {}", e)))?,
+        None => Vec::new(),
+    };
+    
+    let mut chain = vec![primary];
+    for provider in fallbacks {
+        if !provider.is_empty() && !chain.contains(&provider) {
+            chain.push(provider);
         }
-        "google" => {
-            google::call_google(&provider_params.google.api_key, messages).await
+    }
+    
+    Ok(chain)
+}
+
+// Load a provider of the chain and its parameters from settings. The model
+// chosen in `options` only applies to the first provider, since fallbacks
+// use their own configured models.
+fn load_provider(app: &AppHandle, provider: &str, options: &CallOptions, primary: bool) -> Result<(&'static dyn LlmProvider, ProviderConfig), AppError> {
+    let (provider, mut config) = load_provider_config(app, provider)?;
+    if let Some(model) = options.model.as_ref().filter(|_| primary) {
+        provider.set_model(&mut config, model);
+    }
+    config.overrides = options.generation.clone();
+    if provider.supports_tools() {
+        config.tools = options.tools.clone();
+    }
+    provider.validate(&config)?;
+    
+    Ok((provider, config))
+}
+
+// Whether an error means the provider can't answer at all, so the next
+// provider of the chain should be tried: it is unreachable, misconfigured,
+// rejects the credentials or doesn't have the model
+fn should_fall_back(error: &AppError) -> bool {
+    matches!(
+        error.kind,
+        ErrorKind::Network | ErrorKind::Config | ErrorKind::Auth | ErrorKind::ModelNotFound | ErrorKind::Unsupported
+    )
+}
+
+const IMAGES_HINT: &str = "Choose a model that can read images in Settings, or remove the images";
+
+// Refuse images the provider's model can't read. A model missing from the
+// catalog or of unknown capabilities, or a catalog that can't be fetched, is
+// left to the provider.
+async fn check_images(app: &AppHandle, provider: &dyn LlmProvider, config: &ProviderConfig, messages: &[Message]) -> Result<(), AppError> {
+    if messages.iter().all(|m| m.images.is_empty()) {
+        return Ok(());
+    }
+    
+    if !provider.supports_images() {
+        return Err(AppError::new(
+            ErrorKind::Unsupported,
+            format!("{} doesn't support images", provider.display_name()),
+        )
+        .with_hint(IMAGES_HINT));
+    }
+    
+    if !provider.reports_vision() {
+        return Ok(());
+    }
+    
+    let model = provider.model(config);
+    let models = match catalog::list_models(app, &provider_setting(provider, config), false).await {
+        Ok(models) => models,
+        Err(e) => {
+            eprintln!("Warning: Couldn't check whether {} accepts images: {}", model, e);
+            return Ok(());
         }
-        "lmstudio" => {
-            lmstudio::call_lmstudio(&provider_params.lmstudio.url, &provider_params.lmstudio.model, messages).await
+    };
+    
+    match models.iter().find(|m| m.id == model) {
+        Some(info) if info.vision == Some(false) => Err(AppError::new(
+            ErrorKind::Unsupported,
+            format!("Model {} can't read images", model),
+        )
+        .with_hint(IMAGES_HINT)),
+        _ => Ok(()),
+    }
+}
+
+// Messages as sent to a provider: without tools on offer, tool calls and
+// results are sent as text, since the APIs reject them without tool definitions
+fn provider_messages<'a>(config: &ProviderConfig, messages: &'a [Message]) -> Cow<'a, [Message]> {
+    let has_tool_messages = messages.iter().any(|m| m.role == "tool" || !m.tool_calls.is_empty());
+    if config.tools.is_empty() && has_tool_messages {
+        Cow::Owned(flatten_tool_messages(messages))
+    } else {
+        Cow::Borrowed(messages)
+    }
+}
+
+// Tag an error with the provider setting it came from and suggest a fix
+fn provider_error(error: AppError, provider_id: &str) -> AppError {
+    let error = match error.provider {
+        Some(_) => error,
+        None => error.with_provider(provider_id),
+    };
+    if error.hint.is_some() {
+        return error;
+    }
+    
+    let name = get_provider(provider_id)
+        .map(|(provider, _)| provider.display_name())
+        .unwrap_or("the provider");
+    let local = provider_id == "ollama" || provider_id == "lmstudio";
+    
+    let hint = match error.kind {
+        ErrorKind::Auth => format!("Check the {} API key in Settings", name),
+        ErrorKind::Config => format!("Check the {} settings", name),
+        ErrorKind::ModelNotFound => format!("Choose another {} model in Settings", name),
+        ErrorKind::Network if local => format!("Make sure {} is running and the URL in Settings is correct", name),
+        ErrorKind::Network => "Check your internet connection and the proxy settings".to_string(),
+        ErrorKind::Timeout => "Retry, or raise the read timeout in the network settings".to_string(),
+        ErrorKind::RateLimit => "Wait a moment and retry, or add a fallback provider".to_string(),
+        _ => return error,
+    };
+    error.with_hint(hint)
+}
+
+// The error reported when every provider of the chain failed: the first
+// provider's error, since that is the one the user configured, with the
+// messages of all of them
+fn chain_error(errors: Vec<AppError>) -> AppError {
+    let message = errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>().join("; ");
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(first) => AppError { message, ..first },
+        None => AppError::config("No provider configured"),
+    }
+}
+
+// Provider setting identifying the provider and entry, e.g. "openai-compatible:groq"
+fn provider_setting(provider: &dyn LlmProvider, config: &ProviderConfig) -> String {
+    match &config.entry {
+        Some(entry) => format!("{}:{}", provider.id(), entry),
+        None => provider.id().to_string(),
+    }
+}
+
+// Add the estimated cost to the reported token usage
+fn price_usage(provider: &dyn LlmProvider, model: &str, usage: &mut Option<TokenUsage>) {
+    if let Some(usage) = usage {
+        usage.cost = estimate_cost(provider.id(), model, usage);
+    }
+}
+
+// Call LLM - supports multiple providers, moving down the fallback chain
+// when a provider can't answer
+pub async fn call_llm(app: &AppHandle, messages: &[Message], options: &CallOptions) -> Result<Completion, AppError> {
+    let chain = provider_chain(app, options)?;
+    let mut errors: Vec<AppError> = Vec::new();
+    
+    for (index, provider_id) in chain.iter().enumerate() {
+        let result = match load_provider(app, provider_id, options, index == 0) {
+            Ok((provider, config)) => match check_images(app, provider, &config, messages).await {
+                Ok(()) => provider
+                    .complete(&config, &provider_messages(&config, messages))
+                    .await
+                    .map(|completion| (provider, config, completion)),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
         }
-        "custom" => {
-            custom::call_custom(&provider_params.custom.url, messages).await
+        .map_err(|e| provider_error(e, provider_id));
+        
+        match result {
+            Ok((provider, config, mut completion)) => {
+                completion.provider = provider_setting(provider, &config);
+                completion.model = provider.model(&config);
+                price_usage(provider, &completion.model, &mut completion.usage);
+                return Ok(completion);
+            }
+            Err(e) if index + 1 < chain.len() && should_fall_back(&e) => {
+                eprintln!("Warning: {} failed, falling back to {}: {}", provider_id, chain[index + 1], e);
+                errors.push(e);
+            }
+            Err(e) => {
+                errors.push(e);
+                break;
+            }
         }
-        _ => Err(format!("Unknown provider: {}", provider)),
     }
+    
+    Err(chain_error(errors))
 }
 
-pub async fn stream_llm(
-    app: &AppHandle,
-    event_name: &str,
-    messages: &[Message],
-    full_response: &mut String,
-    periodic_save: Option<Box<dyn Fn(&str) -> Result<(), String> + Send + Sync>>,
-    cancel_flag: Arc<AtomicBool>,
-) -> Result<(), String> {
-    // Load settings
-    let settings = load_settings(app)?;
-    let provider = settings
-        .get("provider")
-        .and_then(|v| v.as_str())
-        .unwrap_or("ollama")
-        .to_string();
+// Stream from the first provider of the fallback chain that can answer. A
+// provider is only abandoned before it has streamed anything; each fallback is
+// announced on the stream channel.
+pub async fn stream_llm(app: &AppHandle, messages: &[Message], options: &CallOptions, sink: &mut StreamSink) -> Result<(), AppError> {
+    let chain = provider_chain(app, options)?;
+    let mut errors: Vec<AppError> = Vec::new();
     
-    let provider_params_value = settings
-        .get("provider-params")
-        .ok_or_else(|| "Missing provider-params in settings".to_string())?;
-    
-    let provider_params: crate::models::ProviderParams = serde_json::from_value(provider_params_value.clone())
-        .map_err(|e| format!("Failed to parse provider-params: {}", e))?;
-    
-    match provider.as_str() {
-        "ollama" => {
-            ollama::stream_ollama(
-                app,
-                event_name,
-                &provider_params.ollama.url,
-                &provider_params.ollama.model,
-                messages,
-                full_response,
-                periodic_save,
-                cancel_flag,
-            ).await
-        }
-        "openai" => {
-            openai::stream_openai(
-                app,
-                event_name,
-                &provider_params.openai.api_key,
-                &provider_params.openai.web_search_enabled,
-                messages,
-                full_response,
-                periodic_save,
-                cancel_flag,
-            ).await
+    for (index, provider_id) in chain.iter().enumerate() {
+        let result = match load_provider(app, provider_id, options, index == 0) {
+            Ok((provider, config)) => {
+                // Recorded up front so a cancelled stream still knows its source
+                sink.provider = provider_setting(provider, &config);
+                sink.model = provider.model(&config);
+                
+                match check_images(app, provider, &config, messages).await {
+                    Ok(()) => provider
+                        .stream(&config, &provider_messages(&config, messages), sink)
+                        .await
+                        .map(|()| provider),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
         }
-        _ => {
-            Err(format!("Streaming not yet implemented for provider: {}", provider))
+        .map_err(|e| provider_error(e, provider_id));
+        
+        match result {
+            Ok(provider) => {
+                price_usage(provider, &sink.model, &mut sink.usage);
+                return Ok(());
+            }
+            Err(e) if index + 1 < chain.len() && sink.content.is_empty() && sink.reasoning.is_empty() && should_fall_back(&e) => {
+                sink.reset_attempt(); // Drops tool calls the failed provider started
+                sink.status(StreamStatus::Fallback {
+                    from: provider_id.clone(),
+                    to: chain[index + 1].clone(),
+                    reason: e.message.clone(),
+                });
+                errors.push(e);
+            }
+            Err(e) => {
+                errors.push(e);
+                break;
+            }
         }
     }
+    
+    Err(chain_error(errors))
 }
-
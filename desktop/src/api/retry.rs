@@ -0,0 +1,160 @@
+use std::time::Duration;
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use reqwest::header::HeaderMap;
+
+use crate::api::stream::{StreamSink, StreamStatus};
+
+const MAX_ATTEMPTS: u32 = 4;
+const BASE_DELAY: Duration = Duration::from_secs(1);
+const MAX_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60); // Longer waits fail instead of hanging the chat
+
+// Statuses worth another attempt: rate limits, timeouts and server errors
+// (529 is Anthropic's "overloaded")
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout()
+}
+
+// Delay requested by the server, from `retry-after-ms` (OpenAI) or
+// `Retry-After` as seconds or an HTTP date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(ms) = headers
+        .get("retry-after-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<f64>().ok())
+    {
+        return Some(Duration::from_secs_f64(ms.max(0.0) / 1000.0));
+    }
+    
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Some(Duration::from_secs_f64(seconds.max(0.0)));
+    }
+    
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+// Exponential backoff with jitter: a random delay between half and all of
+// BASE_DELAY * 2^(attempt - 1), capped at MAX_DELAY
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY.saturating_mul(1 << (attempt - 1).min(16)).min(MAX_DELAY);
+    let jitter = rand::thread_rng().gen_range(0.5..=1.0);
+    delay.mul_f64(jitter)
+}
+
+// Delay before retrying a retryable status: the server's `Retry-After`, or
+// backoff when it sent none. None when the server asks for longer than
+// MAX_RETRY_AFTER, so the response is returned instead.
+fn retry_delay(headers: &HeaderMap, attempt: u32) -> Option<Duration> {
+    match retry_after(headers) {
+        Some(wait) if wait > MAX_RETRY_AFTER => None,
+        Some(wait) => Some(wait),
+        None => Some(backoff(attempt)),
+    }
+}
+
+// Send a request, retrying connection failures, timeouts, rate limits and
+// server errors with backoff. A `Retry-After` from the server is honored.
+// The last response is returned whatever its status, so callers report errors
+// as before. Each retry is announced on the stream channel when there is one;
+// retrying only covers sending the request, so a stream is never retried
+// once its first token has been emitted.
+pub async fn send_with_retry(request: RequestBuilder, sink: Option<&StreamSink>) -> Result<Response, reqwest::Error> {
+    let mut attempt = 1;
+    
+    loop {
+        // Requests with a streamed body can't be cloned and are sent once
+        let Some(retry_request) = request.try_clone().filter(|_| attempt < MAX_ATTEMPTS) else {
+            return request.send().await;
+        };
+        
+        let (delay, reason) = match retry_request.send().await {
+            Ok(response) if is_retryable_status(response.status()) => {
+                let Some(delay) = retry_delay(response.headers(), attempt) else {
+                    return Ok(response);
+                };
+                (delay, format!("HTTP {}", response.status()))
+            }
+            Ok(response) => return Ok(response),
+            Err(e) if is_retryable_error(&e) => (backoff(attempt), e.to_string()),
+            Err(e) => return Err(e),
+        };
+        
+        if let Some(sink) = sink {
+            sink.status(StreamStatus::Retrying {
+                attempt: attempt + 1,
+                max_attempts: MAX_ATTEMPTS,
+                delay_ms: delay.as_millis() as u64,
+                reason,
+            });
+        }
+        
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+    
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+    
+    #[test]
+    fn retry_after_seconds_and_milliseconds() {
+        assert_eq!(retry_after(&headers(&[("retry-after", "5")])), Some(Duration::from_secs(5)));
+        assert_eq!(retry_after(&headers(&[("retry-after", " 1.5 ")])), Some(Duration::from_millis(1500)));
+        assert_eq!(retry_after(&headers(&[("retry-after", "-3")])), Some(Duration::ZERO));
+        assert_eq!(
+            retry_after(&headers(&[("retry-after-ms", "250"), ("retry-after", "5")])),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(retry_after(&headers(&[])), None);
+        assert_eq!(retry_after(&headers(&[("retry-after", "soon")])), None);
+    }
+    
+    #[test]
+    fn retry_after_http_date() {
+        let at = chrono::Utc::now() + chrono::Duration::seconds(30);
+        let value = at.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let wait = retry_after(&headers(&[("retry-after", &value)])).unwrap();
+        assert!(wait > Duration::from_secs(27) && wait <= Duration::from_secs(30), "{:?}", wait);
+        
+        let past = retry_after(&headers(&[("retry-after", "Wed, 21 Oct 2015 07:28:00 GMT")]));
+        assert_eq!(past, Some(Duration::ZERO));
+    }
+    
+    #[test]
+    fn retry_delay_gives_up_on_long_waits() {
+        assert_eq!(retry_delay(&headers(&[("retry-after", "60")]), 1), Some(MAX_RETRY_AFTER));
+        assert_eq!(retry_delay(&headers(&[("retry-after", "61")]), 1), None);
+        assert_eq!(retry_delay(&headers(&[("retry-after-ms", "120000")]), 1), None);
+        assert!(retry_delay(&headers(&[]), 1).unwrap() <= BASE_DELAY);
+    }
+    
+    #[test]
+    fn backoff_doubles_within_jitter_bounds() {
+        for attempt in 1..=40 {
+            // 1 s, 2 s, 4 s, ... up to 30 s
+            let full = Duration::from_secs((1u64 << (attempt - 1).min(16)).min(30));
+            for _ in 0..50 {
+                let delay = backoff(attempt);
+                assert!(delay >= full / 2 && delay <= full, "attempt {}: {:?}", attempt, delay);
+            }
+        }
+    }
+}
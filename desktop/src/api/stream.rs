@@ -0,0 +1,315 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use futures_util::StreamExt;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use crate::api::decode::{NdjsonDecoder, SseDecoder, SseEvent, StreamDecoder, TextPart, ThinkTagSplitter};
+use crate::api::http::request_error;
+use crate::models::{AppError, Citation, TokenUsage, ToolCall};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(2); // Save every 2 seconds
+
+// Notices emitted on the stream channel alongside text chunks. The frontend
+// tells them apart from chunks since they are objects rather than strings.
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum StreamStatus {
+    Retrying {
+        attempt: u32,
+        #[serde(rename = "maxAttempts")]
+        max_attempts: u32,
+        #[serde(rename = "delayMs")]
+        delay_ms: u64,
+        reason: String,
+    },
+    // The provider couldn't answer and the next one of the fallback chain is tried
+    Fallback {
+        from: String,
+        to: String,
+        reason: String,
+    },
+    // A tool the model called is running
+    ToolCall {
+        name: String,
+    },
+    // A tool call waits for the user to allow or deny it
+    ToolApproval {
+        name: String,
+        arguments: serde_json::Value,
+    },
+    // Tool results were saved and the model is answering them in a new
+    // assistant message
+    ToolStep,
+    // A chunk of the model's reasoning, streamed apart from the answer
+    Reasoning {
+        text: String,
+    },
+}
+
+// A streamed tool call whose arguments may still be arriving in fragments
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+// Receives the answer and the reasoning streamed so far
+pub type SaveCallback = Box<dyn Fn(&str, &str) -> Result<(), AppError> + Send + Sync>;
+
+// Receives streamed content from a provider: accumulates the full response,
+// forwards each chunk to the frontend and periodically persists the partial message
+pub struct StreamSink {
+    app: AppHandle,
+    event_name: String,
+    pub content: String,
+    pub reasoning: String,
+    pub reasoning_signature: String, // Anthropic's signature of the thinking
+    pub citations: Vec<Citation>,
+    pub usage: Option<TokenUsage>,
+    pub provider: String, // Provider setting and model that produced the stream
+    pub model: String,
+    tool_calls: Vec<PartialToolCall>,
+    think_tags: ThinkTagSplitter,
+    periodic_save: Option<SaveCallback>,
+    cancel_flag: Arc<AtomicBool>,
+    last_save_time: Instant,
+}
+
+impl StreamSink {
+    pub fn new(
+        app: AppHandle,
+        event_name: String,
+        periodic_save: Option<SaveCallback>,
+        cancel_flag: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            app,
+            event_name,
+            content: String::new(),
+            reasoning: String::new(),
+            reasoning_signature: String::new(),
+            citations: Vec::new(),
+            usage: None,
+            provider: String::new(),
+            model: String::new(),
+            tool_calls: Vec::new(),
+            think_tags: ThinkTagSplitter::default(),
+            periodic_save,
+            cancel_flag,
+            last_save_time: Instant::now(),
+        }
+    }
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel_flag.load(Ordering::Relaxed)
+    }
+    
+    // Append a chunk of the answer and emit it on the stream-chunk event
+    pub fn push(&mut self, chunk: &str) -> Result<(), AppError> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+        
+        self.content.push_str(chunk);
+        self.app.emit(&self.event_name, chunk).map_err(|e| format!("Failed to emit chunk: {}", e))?;
+        self.save_periodically();
+        
+        Ok(())
+    }
+    
+    // Append a chunk of reasoning and emit it as a Reasoning status
+    pub fn push_reasoning(&mut self, chunk: &str) {
+        if chunk.is_empty() {
+            return;
+        }
+        
+        self.reasoning.push_str(chunk);
+        self.status(StreamStatus::Reasoning { text: chunk.to_string() });
+        self.save_periodically();
+    }
+    
+    // Append a chunk of text that may carry inline <think> reasoning, as
+    // streamed by some local models
+    pub fn push_tagged(&mut self, chunk: &str) -> Result<(), AppError> {
+        let mut parts = Vec::new();
+        self.think_tags.push(chunk, &mut parts);
+        self.push_parts(parts)
+    }
+    
+    // Pass on text `push_tagged` held back in case it started a tag
+    pub fn finish_tagged(&mut self) -> Result<(), AppError> {
+        let mut parts = Vec::new();
+        self.think_tags.finish(&mut parts);
+        self.push_parts(parts)
+    }
+    
+    fn push_parts(&mut self, parts: Vec<TextPart>) -> Result<(), AppError> {
+        for part in parts {
+            match part {
+                TextPart::Reasoning(text) => self.push_reasoning(&text),
+                TextPart::Answer(text) => self.push(&text)?,
+            }
+        }
+        Ok(())
+    }
+    
+    // Periodic save (every 2 seconds)
+    fn save_periodically(&mut self) {
+        if let Some(ref save_callback) = self.periodic_save {
+            if self.last_save_time.elapsed() >= SAVE_INTERVAL {
+                if let Err(e) = save_callback(&self.content, &self.reasoning) {
+                    eprintln!("Warning: Failed to save partial message: {}", e);
+                }
+                self.last_save_time = Instant::now();
+            }
+        }
+    }
+    
+    // Emit a status notice; failures are only logged since they don't affect the answer
+    pub fn status(&self, status: StreamStatus) {
+        if let Err(e) = self.app.emit(&self.event_name, status) {
+            eprintln!("Warning: Failed to emit stream status: {}", e);
+        }
+    }
+    
+    // Record a tool call received whole
+    pub fn add_tool_call(&mut self, call: ToolCall) {
+        self.tool_calls.push(PartialToolCall {
+            id: call.id,
+            name: call.name,
+            arguments: call.arguments.to_string(),
+        });
+    }
+    
+    // Begin a tool call whose arguments arrive with `push_tool_arguments`
+    pub fn start_tool_call(&mut self, id: &str, name: &str) {
+        self.tool_calls.push(PartialToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments: String::new(),
+        });
+    }
+    
+    // Append a fragment of the JSON arguments of the tool call at `index`, in
+    // the order calls were started, or of the last one
+    pub fn push_tool_arguments(&mut self, index: Option<usize>, fragment: &str) {
+        let call = match index {
+            Some(index) => self.tool_calls.get_mut(index),
+            None => self.tool_calls.last_mut(),
+        };
+        if let Some(call) = call {
+            call.arguments.push_str(fragment);
+        }
+    }
+    
+    // Tool calls received so far. Ids are generated where the provider sent
+    // none; arguments that aren't valid JSON are passed on as a string so the
+    // tool can report the problem to the model.
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        self.tool_calls
+            .iter()
+            .enumerate()
+            .map(|(index, call)| ToolCall {
+                id: if call.id.is_empty() { format!("call_{}", index) } else { call.id.clone() },
+                name: call.name.clone(),
+                arguments: match call.arguments.trim() {
+                    "" => serde_json::json!({}),
+                    arguments => serde_json::from_str(arguments)
+                        .unwrap_or_else(|_| serde_json::Value::String(arguments.to_string())),
+                },
+            })
+            .collect()
+    }
+    
+    // Start over for the next step of a tool loop
+    pub fn reset(&mut self) {
+        self.reset_attempt();
+        self.citations.clear();
+    }
+    
+    // Drop what a failed provider streamed before the next one is tried,
+    // keeping the citations tools gathered in earlier steps
+    pub fn reset_attempt(&mut self) {
+        self.content.clear();
+        self.reasoning.clear();
+        self.reasoning_signature.clear();
+        self.usage = None;
+        self.tool_calls.clear();
+        self.think_tags = ThinkTagSplitter::default();
+        self.last_save_time = Instant::now();
+    }
+    
+    // Record sources returned by the provider, skipping duplicate URLs
+    pub fn add_citations(&mut self, citations: Vec<Citation>) {
+        for citation in citations {
+            if !self.citations.iter().any(|c| c.url == citation.url) {
+                self.citations.push(citation);
+            }
+        }
+    }
+}
+
+// Returned by stream handlers to tell `read_stream` whether the stream is finished
+pub enum StreamControl {
+    Continue,
+    Done,
+}
+
+// Read a streaming response through `D`, passing each decoded item to the
+// handler and stopping early when it reports the end of the stream or the
+// user cancels. Text `push_tagged` held back is passed on at the end.
+pub async fn read_stream<D, F>(response: reqwest::Response, sink: &mut StreamSink, mut on_item: F) -> Result<(), AppError>
+where
+    D: StreamDecoder,
+    F: FnMut(&mut StreamSink, &D::Item) -> Result<StreamControl, AppError> + Send,
+{
+    let mut stream = response.bytes_stream();
+    let mut decoder = D::default();
+    let mut items = Vec::new();
+    
+    while let Some(chunk) = stream.next().await {
+        // Check if aborted
+        if sink.is_cancelled() {
+            return Ok(());
+        }
+        
+        let chunk = chunk.map_err(|e| request_error(&e, format!("Stream error: {}", e)))?;
+        decoder.push(&chunk, &mut items);
+        
+        for item in items.drain(..) {
+            if let StreamControl::Done = on_item(sink, &item)? {
+                return sink.finish_tagged();
+            }
+        }
+    }
+    
+    if sink.is_cancelled() {
+        return Ok(());
+    }
+    
+    decoder.finish(&mut items);
+    for item in &items {
+        if let StreamControl::Done = on_item(sink, item)? {
+            break;
+        }
+    }
+    
+    sink.finish_tagged()
+}
+
+// Read a server-sent events response event by event
+pub async fn read_sse<F>(response: reqwest::Response, sink: &mut StreamSink, on_event: F) -> Result<(), AppError>
+where
+    F: FnMut(&mut StreamSink, &SseEvent) -> Result<StreamControl, AppError> + Send,
+{
+    read_stream::<SseDecoder, _>(response, sink, on_event).await
+}
+
+// Read a newline-delimited JSON response line by line
+pub async fn read_ndjson<F>(response: reqwest::Response, sink: &mut StreamSink, on_line: F) -> Result<(), AppError>
+where
+    F: FnMut(&mut StreamSink, &String) -> Result<StreamControl, AppError> + Send,
+{
+    read_stream::<NdjsonDecoder, _>(response, sink, on_line).await
+}
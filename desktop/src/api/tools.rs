@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use serde::Serialize;
+use tauri::AppHandle;
+use chrono::{Local, Utc};
+use tokio::sync::oneshot;
+
+use crate::models::{AppError, Citation, Message, ToolCall};
+use crate::storage::settings::{load_settings, load_web_search_settings};
+use crate::api::web_search::WebSearchTool;
+use crate::mcp::load_mcp_tools;
+
+// Most model requests in one answer: the last one is sent without tools, so
+// the model has to answer with what the tools returned so far
+pub const MAX_TOOL_STEPS: usize = 8;
+
+// Longer results are cut, so one tool can't fill the context window
+const MAX_RESULT_CHARS: usize = 20_000;
+
+// Limits on the model's `calculate` expressions, so deep nesting can't
+// overflow the stack of the recursive evaluator
+const MAX_EXPRESSION_CHARS: usize = 1_000;
+const MAX_NESTING: usize = 64;
+
+// A tool as advertised to the model: its name, what it does and a JSON schema
+// of its arguments
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl ToolSpec {
+    // Whether the arguments schema declares any property; Gemini rejects an
+    // object schema without properties
+    pub fn has_parameters(&self) -> bool {
+        self.parameters
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .map(|p| !p.is_empty())
+            .unwrap_or(false)
+    }
+}
+
+// What a tool returns: text sent back to the model, and the sources it came
+// from, which are stored on the answer
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    pub text: String,
+    pub citations: Vec<Citation>,
+}
+
+impl From<String> for ToolOutput {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            citations: Vec::new(),
+        }
+    }
+}
+
+// A tool the model can call. An error is sent back to the model too, so it
+// can correct its arguments.
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn spec(&self) -> ToolSpec;
+    
+    // Whether the user has to allow each call before it runs
+    fn needs_approval(&self) -> bool {
+        false
+    }
+    
+    async fn execute(&self, app: &AppHandle, arguments: &serde_json::Value) -> Result<ToolOutput, AppError>;
+}
+
+// The tools offered for one answer
+#[derive(Default, Clone)]
+pub struct ToolSet {
+    tools: Vec<Arc<dyn Tool>>,
+}
+
+impl ToolSet {
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+    
+    pub fn specs(&self) -> Vec<ToolSpec> {
+        self.tools.iter().map(|tool| tool.spec()).collect()
+    }
+    
+    fn find(&self, name: &str) -> Option<&Arc<dyn Tool>> {
+        self.tools.iter().find(|tool| tool.spec().name == name)
+    }
+    
+    pub fn needs_approval(&self, call: &ToolCall) -> bool {
+        self.find(&call.name).map(|tool| tool.needs_approval()).unwrap_or(false)
+    }
+    
+    // Run a tool call and return what is sent back to the model
+    pub async fn execute(&self, app: &AppHandle, call: &ToolCall) -> ToolOutput {
+        let Some(tool) = self.find(&call.name) else {
+            return format!("Error: unknown tool {}", call.name).into();
+        };
+        if !call.arguments.is_object() {
+            return format!("Error: the arguments must be a JSON object, got {}", call.arguments).into();
+        }
+        
+        match tool.execute(app, &call.arguments).await {
+            Ok(mut output) => {
+                if output.text.chars().count() > MAX_RESULT_CHARS {
+                    let cut: String = output.text.chars().take(MAX_RESULT_CHARS).collect();
+                    output.text = format!("{}\n[Result truncated]", cut);
+                }
+                output
+            }
+            Err(e) => format!("Error: {}", e).into(),
+        }
+    }
+}
+
+// Tools offered to the model when the "tools-enabled" setting is on: the
+// built-in tools (web search once a search URL is set) and those of the
+// enabled MCP servers
+pub async fn load_tools(app: &AppHandle) -> Result<ToolSet, AppError> {
+    let settings = load_settings(app)?;
+    let enabled = settings
+        .get("tools-enabled")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if !enabled {
+        return Ok(ToolSet::default());
+    }
+    
+    let mut tools: Vec<Arc<dyn Tool>> = vec![Arc::new(CurrentTimeTool), Arc::new(CalculateTool)];
+    let web_search = load_web_search_settings(app)?;
+    if !web_search.url.trim().is_empty() {
+        tools.push(Arc::new(WebSearchTool::new(web_search)));
+    }
+    tools.extend(load_mcp_tools(app).await?);
+    Ok(ToolSet { tools })
+}
+
+// Tool calls waiting for the user to allow or deny them, by conversation
+#[derive(Default)]
+pub struct ToolApprovals {
+    pending: Mutex<HashMap<String, oneshot::Sender<bool>>>,
+}
+
+impl ToolApprovals {
+    // Wait for the user's answer about the call the conversation is showing.
+    // Stopping the answer drops the wait, which counts as a denial.
+    pub async fn request(&self, conversation_id: &str) -> bool {
+        let (sender, receiver) = oneshot::channel();
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(conversation_id.to_string(), sender);
+        }
+        receiver.await.unwrap_or(false)
+    }
+    
+    pub fn answer(&self, conversation_id: &str, approved: bool) -> Result<(), AppError> {
+        let sender = self
+            .pending
+            .lock()
+            .ok()
+            .and_then(|mut pending| pending.remove(conversation_id))
+            .ok_or_else(|| AppError::not_found("No tool call is waiting for approval"))?;
+        let _ = sender.send(approved);
+        Ok(())
+    }
+    
+    // Forget a wait whose answer was stopped
+    pub fn cancel(&self, conversation_id: &str) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(conversation_id);
+        }
+    }
+}
+
+// Keep only complete tool exchanges: a call whose result is missing (the
+// answer was stopped while the tool ran) or a result whose call was left out
+// by the context policy would be rejected by the providers
+pub fn paired_tool_messages(messages: Vec<Message>) -> Vec<Message> {
+    let result_ids: Vec<String> = messages
+        .iter()
+        .filter(|m| m.role == "tool")
+        .map(|m| m.tool_call_id.clone())
+        .collect();
+    let call_ids: Vec<String> = messages
+        .iter()
+        .flat_map(|m| m.tool_calls.iter().map(|call| call.id.clone()))
+        .filter(|id| result_ids.contains(id))
+        .collect();
+    
+    messages
+        .into_iter()
+        .filter_map(|mut m| {
+            if m.role == "tool" {
+                return call_ids.contains(&m.tool_call_id).then_some(m);
+            }
+            if !m.tool_calls.is_empty() {
+                m.tool_calls.retain(|call| call_ids.contains(&call.id));
+                if m.tool_calls.is_empty() && m.content.is_empty() {
+                    return None;
+                }
+            }
+            Some(m)
+        })
+        .collect()
+}
+
+// History for a provider without tool support: calls and results are
+// written out as text so the model still sees what happened
+pub fn flatten_tool_messages(messages: &[Message]) -> Vec<Message> {
+    messages
+        .iter()
+        .map(|m| {
+            if m.role == "tool" {
+                return Message {
+                    role: "user".to_string(),
+                    content: format!("[Result of tool {}]\n{}", m.tool_name, m.content),
+                    ..Default::default()
+                };
+            }
+            
+            let mut message = m.clone();
+            for call in message.tool_calls.drain(..) {
+                if !message.content.is_empty() {
+                    message.content.push_str("\n\n");
+                }
+                message.content.push_str(&format!("[Called tool {} with {}]", call.name, call.arguments));
+            }
+            message
+        })
+        .collect()
+}
+
+// Current date and time, which models otherwise don't know
+struct CurrentTimeTool;
+
+#[async_trait]
+impl Tool for CurrentTimeTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "get_current_time".to_string(),
+            description: "Get the current date, time and weekday in the user's time zone and in UTC.".to_string(),
+            parameters: serde_json::json!({ "type": "object", "properties": {} }),
+        }
+    }
+    
+    async fn execute(&self, _app: &AppHandle, _arguments: &serde_json::Value) -> Result<ToolOutput, AppError> {
+        let local = Local::now();
+        Ok(format!(
+            "Local time: {} ({})\nUTC: {}",
+            local.format("%Y-%m-%d %H:%M:%S %:z"),
+            local.format("%A"),
+            Utc::now().format("%Y-%m-%d %H:%M:%S"),
+        )
+        .into())
+    }
+}
+
+// Arithmetic, which models get wrong on long numbers
+struct CalculateTool;
+
+#[async_trait]
+impl Tool for CalculateTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "calculate".to_string(),
+            description: "Evaluate an arithmetic expression with + - * / % ^ and parentheses, e.g. \"(1.5 + 2) * 3^2\".".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "expression": { "type": "string", "description": "The expression to evaluate" }
+                },
+                "required": ["expression"]
+            }),
+        }
+    }
+    
+    async fn execute(&self, _app: &AppHandle, arguments: &serde_json::Value) -> Result<ToolOutput, AppError> {
+        let expression = arguments
+            .get("expression")
+            .and_then(|e| e.as_str())
+            .ok_or("Missing expression")?;
+        let value = Calculator::new(expression).evaluate()?;
+        Ok(value.to_string().into())
+    }
+}
+
+// Recursive descent evaluator for `calculate`:
+// expression = term (("+" | "-") term)*, term = unary (("*" | "/" | "%") unary)*,
+// unary = "-" unary | power, power = primary ("^" unary)?,
+// primary = number | "(" expression ")". Signs apply after exponents, so
+// -2^2 is -4 as in standard notation.
+struct Calculator {
+    chars: Vec<char>,
+    position: usize,
+    depth: usize, // Parentheses, signs and exponents currently open
+}
+
+impl Calculator {
+    fn new(expression: &str) -> Self {
+        Self {
+            chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+            position: 0,
+            depth: 0,
+        }
+    }
+    
+    fn evaluate(mut self) -> Result<f64, String> {
+        if self.chars.len() > MAX_EXPRESSION_CHARS {
+            return Err(format!("The expression is longer than {} characters", MAX_EXPRESSION_CHARS));
+        }
+        
+        let value = self.expression()?;
+        if let Some(c) = self.peek() {
+            return Err(format!("Unexpected '{}' in expression", c));
+        }
+        if !value.is_finite() {
+            return Err("The result is not a finite number".to_string());
+        }
+        Ok(value)
+    }
+    
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+    
+    fn next_if(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+    
+    // Evaluate a nested part, refusing to go deeper than MAX_NESTING
+    fn nested(&mut self, evaluate: fn(&mut Self) -> Result<f64, String>) -> Result<f64, String> {
+        if self.depth >= MAX_NESTING {
+            return Err(format!("The expression is nested deeper than {} levels", MAX_NESTING));
+        }
+        
+        self.depth += 1;
+        let value = evaluate(self);
+        self.depth -= 1;
+        value
+    }
+    
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.next_if('+') {
+                value += self.term()?;
+            } else if self.next_if('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+    
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.next_if('*') {
+                value *= self.unary()?;
+            } else if self.next_if('/') {
+                value /= self.unary()?;
+            } else if self.next_if('%') {
+                value %= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+    
+    fn unary(&mut self) -> Result<f64, String> {
+        if self.next_if('-') {
+            return Ok(-self.nested(Self::unary)?);
+        }
+        self.power()
+    }
+    
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.primary()?;
+        if self.next_if('^') {
+            Ok(base.powf(self.nested(Self::unary)?))
+        } else {
+            Ok(base)
+        }
+    }
+    
+    fn primary(&mut self) -> Result<f64, String> {
+        if self.next_if('(') {
+            let value = self.nested(Self::expression)?;
+            if !self.next_if(')') {
+                return Err("Missing ')' in expression".to_string());
+            }
+            return Ok(value);
+        }
+        
+        let start = self.position;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+        let number: String = self.chars[start..self.position].iter().collect();
+        number.parse().map_err(|_| match self.peek() {
+            Some(c) if number.is_empty() => format!("Unexpected '{}' in expression", c),
+            None if number.is_empty() => "Incomplete expression".to_string(),
+            _ => format!("Invalid number {}", number),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn calculate(expression: &str) -> Result<f64, String> {
+        Calculator::new(expression).evaluate()
+    }
+    
+    #[test]
+    fn calculator_precedence() {
+        assert_eq!(calculate("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(calculate("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(calculate("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(calculate("2 ^ 3 ^ 2"), Ok(512.0));
+        assert_eq!(calculate("-2 ^ 2"), Ok(-4.0));
+        assert_eq!(calculate("(-2) ^ 2"), Ok(4.0));
+        assert_eq!(calculate("2 ^ -1"), Ok(0.5));
+        assert_eq!(calculate("7 % 4 * 2"), Ok(6.0));
+        assert_eq!(calculate("1.5 / 0.5"), Ok(3.0));
+    }
+    
+    #[test]
+    fn calculator_errors() {
+        assert_eq!(calculate("1 +"), Err("Incomplete expression".to_string()));
+        assert_eq!(calculate("(1 + 2"), Err("Missing ')' in expression".to_string()));
+        assert_eq!(calculate("2 * x"), Err("Unexpected 'x' in expression".to_string()));
+        assert_eq!(calculate("1.2.3"), Err("Invalid number 1.2.3".to_string()));
+        assert_eq!(calculate("1 / 0"), Err("The result is not a finite number".to_string()));
+    }
+    
+    #[test]
+    fn calculator_limits_nesting_and_length() {
+        let nested = format!("{}1{}", "(".repeat(MAX_NESTING), ")".repeat(MAX_NESTING));
+        assert_eq!(calculate(&nested), Ok(1.0));
+        
+        let too_deep = format!("{}1{}", "(".repeat(MAX_NESTING + 1), ")".repeat(MAX_NESTING + 1));
+        assert!(calculate(&too_deep).unwrap_err().contains("nested"));
+        assert!(calculate(&format!("{}1", "-".repeat(900))).unwrap_err().contains("nested"));
+        assert!(calculate(&"2^".repeat(300)).unwrap_err().contains("nested"));
+        
+        let long = format!("({}1)", "-".repeat(100_000));
+        assert!(calculate(&long).unwrap_err().contains("longer"));
+    }
+}
@@ -0,0 +1,466 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use futures::future::join_all;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tauri::{AppHandle, Manager};
+
+use crate::api::http::{error_response, request_error, HttpClient};
+use crate::api::tools::{Tool, ToolOutput, ToolSpec};
+use crate::models::{AppError, Citation, WebSearchSettings};
+
+const MAX_RESULTS: usize = 10;
+const PAGE_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_PAGE_BYTES: usize = 2 * 1024 * 1024;
+const MAX_PAGE_CHARS: usize = 3_000; // Per page, so every fetched page fits in one tool result
+
+// Elements whose content is not part of the readable text of a page
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "head", "script", "style", "noscript", "template", "svg", "iframe", "nav", "header", "footer", "aside", "form",
+];
+
+// Elements that start a new line of text
+const BLOCK_ELEMENTS: &[&str] = &[
+    "p", "div", "br", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6", "section", "article", "blockquote", "pre", "table",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    pub snippet: String,
+    pub page_text: Option<String>,
+}
+
+// Response in SearXNG's JSON format. The aliases accept the field names
+// other search APIs commonly use.
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    #[serde(default)]
+    results: Vec<RawSearchResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawSearchResult {
+    #[serde(default, alias = "link", alias = "href")]
+    url: String,
+    #[serde(default, alias = "name")]
+    title: String,
+    #[serde(default, alias = "snippet", alias = "description")]
+    content: String,
+}
+
+// Endpoint for a search URL setting: SearXNG answers on /search, so a bare
+// instance URL gets that path; any other path is used as it is
+fn search_endpoint(url: &str) -> Result<reqwest::Url, AppError> {
+    let mut endpoint = reqwest::Url::parse(url.trim())
+        .map_err(|e| AppError::config(format!("Invalid web search URL {}: {}", url, e)))?;
+    if endpoint.path() == "/" {
+        endpoint.set_path("/search");
+    }
+    Ok(endpoint)
+}
+
+// Query the search API and return up to `settings.max_results` results, with
+// the text of their pages when `settings.fetch_pages` is set. A page that
+// can't be fetched keeps only its snippet.
+pub async fn search(client: &reqwest::Client, settings: &WebSearchSettings, query: &str) -> Result<Vec<SearchResult>, AppError> {
+    let endpoint = search_endpoint(&settings.url)?;
+    
+    let response = client
+        .get(endpoint)
+        .query(&[("q", query), ("format", "json")])
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .map_err(|e| request_error(&e, format!("Failed to connect to the search API at {}: {}", settings.url, e)))?;
+    
+    if !response.status().is_success() {
+        return Err(error_response(response, "Search").await);
+    }
+    
+    let search_response: SearchResponse = response
+        .json()
+        .await
+        .map_err(|e| AppError::invalid_response(format!("Failed to parse search results (is the JSON format enabled?): {}", e)))?;
+    
+    let mut results: Vec<SearchResult> = search_response
+        .results
+        .into_iter()
+        .filter(|r| !r.url.is_empty())
+        .take(settings.max_results.clamp(1, MAX_RESULTS))
+        .map(|r| SearchResult {
+            title: r.title.trim().to_string(),
+            url: r.url,
+            snippet: r.content.trim().to_string(),
+            page_text: None,
+        })
+        .collect();
+    
+    if settings.fetch_pages {
+        let pages = join_all(results.iter().map(|r| fetch_page_text(client, &r.url))).await;
+        for (result, page) in results.iter_mut().zip(pages) {
+            match page {
+                Ok(text) if !text.is_empty() => result.page_text = Some(text),
+                Ok(_) => {}
+                Err(e) => eprintln!("Warning: Failed to fetch {}: {}", result.url, e),
+            }
+        }
+    }
+    
+    Ok(results)
+}
+
+// Readable text of an HTML or plain text page, cut to MAX_PAGE_CHARS. Pages
+// larger than MAX_PAGE_BYTES are skipped, whether or not they say so up front.
+async fn fetch_page_text(client: &reqwest::Client, url: &str) -> Result<String, AppError> {
+    let response = client
+        .get(url)
+        .timeout(PAGE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| request_error(&e, e.to_string()))?;
+    
+    if !response.status().is_success() {
+        return Err(AppError::from_status(response.status().as_u16(), format!("status {}", response.status())));
+    }
+    if response.content_length().is_some_and(|length| length as usize > MAX_PAGE_BYTES) {
+        return Ok(String::new());
+    }
+    
+    let content_type = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("text/html")
+        .to_string();
+    let is_html = content_type.starts_with("text/html") || content_type.contains("xhtml");
+    if !is_html && !content_type.starts_with("text/") {
+        return Ok(String::new());
+    }
+    
+    let mut bytes = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| request_error(&e, e.to_string()))?;
+        if bytes.len() + chunk.len() > MAX_PAGE_BYTES {
+            return Ok(String::new());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    let body = String::from_utf8_lossy(&bytes);
+    
+    let text = if is_html { html_to_text(&body) } else { body.into_owned() };
+    
+    Ok(text.chars().take(MAX_PAGE_CHARS).collect())
+}
+
+// Text of an HTML page: tags are dropped, non-text elements (scripts,
+// navigation, footers) with their content, block elements become line
+// breaks, entities are decoded and whitespace is collapsed
+pub fn html_to_text(html: &str) -> String {
+    // Same byte offsets as `html`, since only ASCII letters change
+    let lower = html.to_ascii_lowercase();
+    let mut text = String::new();
+    let mut position = 0;
+    
+    while let Some(offset) = lower[position..].find('<') {
+        let tag_start = position + offset;
+        text.push_str(&html[position..tag_start]);
+        
+        let tag = &lower[tag_start + 1..];
+        if tag.starts_with("!--") {
+            position = match tag.find("-->") {
+                Some(end) => tag_start + 1 + end + 3,
+                None => lower.len(),
+            };
+            continue;
+        }
+        
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let Some(tag_end) = tag.find('>') else {
+            break;
+        };
+        position = tag_start + 1 + tag_end + 1;
+        
+        if !closing && SKIPPED_ELEMENTS.contains(&name.as_str()) && !tag[..tag_end].ends_with('/') {
+            let end_tag = format!("</{}", name);
+            position = match lower[position..].find(&end_tag) {
+                Some(end) => {
+                    let end = position + end;
+                    lower[end..].find('>').map(|close| end + close + 1).unwrap_or(lower.len())
+                }
+                None => lower.len(),
+            };
+        }
+        if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            text.push('\n');
+        } else {
+            text.push(' ');
+        }
+    }
+    if position < html.len() {
+        text.push_str(&html[position..]);
+    }
+    
+    decode_entities(&text)
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Decode named entities common in text and numeric character references
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        
+        let entity = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        });
+        
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Results as text for the model, numbered so it can refer to its sources
+fn format_results(query: &str, results: &[SearchResult]) -> String {
+    if results.is_empty() {
+        return format!("No results for \"{}\".", query);
+    }
+    
+    results
+        .iter()
+        .enumerate()
+        .map(|(index, result)| {
+            let mut entry = format!("[{}] {}\nURL: {}", index + 1, result.title, result.url);
+            if !result.snippet.is_empty() {
+                entry.push_str(&format!("\n{}", result.snippet));
+            }
+            if let Some(page_text) = &result.page_text {
+                entry.push_str(&format!("\nPage text:\n{}", page_text));
+            }
+            entry
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+// Web search for models without a built-in one, through the search API in
+// the "web-search" setting. The results are stored as the answer's citations.
+pub struct WebSearchTool {
+    settings: WebSearchSettings,
+}
+
+impl WebSearchTool {
+    pub fn new(settings: WebSearchSettings) -> Self {
+        Self { settings }
+    }
+}
+
+#[async_trait]
+impl Tool for WebSearchTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "web_search".to_string(),
+            description: "Search the web for current information. Returns numbered results with titles, URLs and snippets; cite the URLs you use.".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "The search query" }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+    
+    async fn execute(&self, app: &AppHandle, arguments: &serde_json::Value) -> Result<ToolOutput, AppError> {
+        let query = arguments
+            .get("query")
+            .and_then(|q| q.as_str())
+            .map(|q| q.trim())
+            .filter(|q| !q.is_empty())
+            .ok_or("Missing query")?;
+        
+        let client = app.state::<HttpClient>().get();
+        let results = search(&client, &self.settings, query).await?;
+        
+        Ok(ToolOutput {
+            text: format_results(query, &results),
+            citations: results
+                .into_iter()
+                .map(|result| Citation {
+                    url: result.url,
+                    title: result.title,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    
+    // Serve `responses` in order, one connection each, and return the base URL
+    // and the request lines received
+    async fn stub_server(responses: Vec<(&'static str, String)>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (content_type, body) in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = vec![0; 4096];
+                let read = socket.read(&mut buffer).await.unwrap();
+                let request = String::from_utf8_lossy(&buffer[..read]).to_string();
+                requests.push(request.lines().next().unwrap_or_default().to_string());
+                
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    content_type,
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+        
+        (url, handle)
+    }
+    
+    fn settings(url: &str, max_results: usize, fetch_pages: bool) -> WebSearchSettings {
+        WebSearchSettings {
+            url: url.to_string(),
+            max_results,
+            fetch_pages,
+        }
+    }
+    
+    #[tokio::test]
+    async fn searches_searxng_and_keeps_top_results() {
+        let body = serde_json::json!({
+            "results": [
+                { "url": "https://a.example/", "title": "A", "content": "First" },
+                { "url": "", "title": "No URL" },
+                { "link": "https://b.example/", "name": "B", "snippet": "Second" },
+                { "url": "https://c.example/", "title": "C" }
+            ]
+        })
+        .to_string();
+        let (url, server) = stub_server(vec![("application/json", body)]).await;
+        
+        let results = search(&reqwest::Client::new(), &settings(&url, 2, false), "rust async").await.unwrap();
+        
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].title, "A");
+        assert_eq!(results[0].snippet, "First");
+        assert_eq!(results[1].url, "https://b.example/");
+        assert_eq!(results[1].snippet, "Second");
+        
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /search?q=rust+async&format=json "), "{}", requests[0]);
+    }
+    
+    #[tokio::test]
+    async fn fetches_page_text() {
+        let (page_url, page_server) = stub_server(vec![(
+            "text/html; charset=utf-8",
+            "<html><body><p>Hello &amp; welcome</p><script>x()</script></body></html>".to_string(),
+        )])
+        .await;
+        let body = serde_json::json!({ "results": [{ "url": format!("{}/page", page_url), "title": "Page" }] }).to_string();
+        let (url, server) = stub_server(vec![("application/json", body)]).await;
+        
+        let results = search(&reqwest::Client::new(), &settings(&url, 5, true), "hello").await.unwrap();
+        
+        assert_eq!(results[0].page_text.as_deref(), Some("Hello & welcome"));
+        server.await.unwrap();
+        page_server.await.unwrap();
+    }
+    
+    #[tokio::test]
+    async fn skips_pages_over_the_size_limit_without_length() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/page", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = vec![0; 4096];
+            let read = socket.read(&mut buffer).await.unwrap();
+            assert!(read > 0);
+            
+            // No Content-Length: the body ends when the connection closes
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n").await.unwrap();
+            let chunk = vec![b'a'; 64 * 1024];
+            for _ in 0..(MAX_PAGE_BYTES / chunk.len() + 2) {
+                if socket.write_all(&chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+        
+        let text = fetch_page_text(&reqwest::Client::new(), &url).await.unwrap();
+        
+        assert_eq!(text, "");
+        server.await.unwrap();
+    }
+    
+    #[test]
+    fn keeps_custom_search_paths() {
+        assert_eq!(search_endpoint("http://localhost:8888").unwrap().path(), "/search");
+        assert_eq!(search_endpoint("https://example.com/api/search").unwrap().path(), "/api/search");
+    }
+    
+    #[test]
+    fn extracts_readable_text() {
+        let html = "<html><head><title>T</title><style>p{}</style></head><body>\
+            <nav>Menu</nav><h1>Title</h1><p>One <b>bold</b>&nbsp;word &#169; &#x41;</p>\
+            <!-- hidden --><div>Two</div><footer>Footer</footer></body></html>";
+        assert_eq!(html_to_text(html), "Title\nOne bold word © A\nTwo");
+    }
+    
+    #[test]
+    fn leaves_unknown_entities() {
+        assert_eq!(decode_entities("a & b &unknown; &#xZZ; &lt;"), "a & b &unknown; &#xZZ; <");
+    }
+}
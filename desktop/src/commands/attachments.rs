@@ -0,0 +1,39 @@
+use tauri::AppHandle;
+use base64::{Engine as _, engine::general_purpose};
+use crate::models::{AppError, ErrorKind, FileAttachment, ImageAttachment};
+use crate::storage::attachments::{save_image, load_image_data};
+use crate::storage::documents::extract_document;
+
+// Decode base64 file data sent by the frontend, bare or as a data URL
+fn decode_data(data: &str) -> Result<Vec<u8>, AppError> {
+    let data = match data.split_once(";base64,") {
+        Some((_, data)) => data,
+        None => data,
+    };
+    general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| AppError::new(ErrorKind::InvalidRequest, format!("Invalid file data: {}", e)))
+}
+
+// Store an image picked or pasted in the input (base64 data) and return the
+// reference to send with the message
+#[tauri::command]
+pub fn attach_image(app: AppHandle, data: String, mime_type: String, name: Option<String>) -> Result<ImageAttachment, AppError> {
+    let bytes = decode_data(&data)?;
+    save_image(&app, &bytes, &mime_type, name.as_deref().unwrap_or_default())
+}
+
+// Data URL of a stored image, for showing it in a message
+#[tauri::command]
+pub fn load_image(app: AppHandle, file: String, mime_type: String) -> Result<String, AppError> {
+    let data = load_image_data(&app, &file)?;
+    Ok(format!("data:{};base64,{}", mime_type, data))
+}
+
+// Extract the text of a document picked in the input (base64 data) to send
+// with the message
+#[tauri::command]
+pub fn attach_file(name: String, data: String) -> Result<FileAttachment, AppError> {
+    let bytes = decode_data(&data)?;
+    extract_document(&name, &bytes)
+}
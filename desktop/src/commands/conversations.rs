@@ -1,5 +1,5 @@
 use tauri::AppHandle;
-use crate::models::Conversation;
+use crate::models::{AppError, ContextPolicy, Conversation, FileAttachment, GenerationParams, ImageAttachment};
 use crate::storage::conversations::{
     save_conversation as save_conversation_storage,
     load_conversation as load_conversation_storage,
@@ -9,39 +9,91 @@ use crate::storage::conversations::{
     get_conversation_history as get_conversation_history_storage,
     create_conversation as create_conversation_storage,
 };
+use crate::storage::settings::find_persona;
+use crate::storage::attachments::remove_unused_images;
 
 #[tauri::command]
-pub fn save_conversation(app: AppHandle, conversation: Conversation) -> Result<(), String> {
+pub fn save_conversation(app: AppHandle, conversation: Conversation) -> Result<(), AppError> {
     save_conversation_storage(&app, &conversation)
 }
 
 #[tauri::command]
-pub fn load_conversation(app: AppHandle, conversation_id: String) -> Result<Conversation, String> {
+pub fn load_conversation(app: AppHandle, conversation_id: String) -> Result<Conversation, AppError> {
     load_conversation_storage(&app, &conversation_id)
 }
 
 #[tauri::command]
-pub fn list_conversations(app: AppHandle) -> Result<Vec<Conversation>, String> {
+pub fn list_conversations(app: AppHandle) -> Result<Vec<Conversation>, AppError> {
     list_conversations_storage(&app)
 }
 
 #[tauri::command]
-pub fn delete_conversation(app: AppHandle, conversation_id: String) -> Result<(), String> {
-    delete_conversation_storage(&app, &conversation_id)
+pub fn delete_conversation(app: AppHandle, conversation_id: String) -> Result<(), AppError> {
+    delete_conversation_storage(&app, &conversation_id)?;
+    clean_up_images(&app);
+    Ok(())
 }
 
 #[tauri::command]
-pub fn delete_all_conversations(app: AppHandle) -> Result<(), String> {
-    delete_all_conversations_storage(&app)
+pub fn delete_all_conversations(app: AppHandle) -> Result<(), AppError> {
+    delete_all_conversations_storage(&app)?;
+    clean_up_images(&app);
+    Ok(())
+}
+
+// The conversations are already gone, so a failed cleanup is only logged
+fn clean_up_images(app: &AppHandle) {
+    if let Err(e) = remove_unused_images(app) {
+        eprintln!("Warning: Failed to remove unused images: {}", e);
+    }
 }
 
 #[tauri::command]
-pub fn get_conversation_history(app: AppHandle) -> Result<Vec<crate::models::ConversationMetadata>, String> {
+pub fn get_conversation_history(app: AppHandle) -> Result<Vec<crate::models::ConversationMetadata>, AppError> {
     get_conversation_history_storage(&app)
 }
 
 #[tauri::command]
-pub fn create_conversation(app: AppHandle, user_message: String) -> Result<String, String> {
-    create_conversation_storage(&app, &user_message)
+pub fn create_conversation(app: AppHandle, user_message: String, images: Option<Vec<ImageAttachment>>, files: Option<Vec<FileAttachment>>, persona: Option<String>) -> Result<String, AppError> {
+    // Start with a persona only if it exists
+    let persona = persona.filter(|name| !name.is_empty());
+    if let Some(name) = &persona {
+        if find_persona(&app, name)?.is_none() {
+            return Err(AppError::not_found(format!("Persona {} not found", name)));
+        }
+    }
+    
+    create_conversation_storage(&app, &user_message, images.unwrap_or_default(), files.unwrap_or_default(), persona)
+}
+
+// Set the generation parameters that override the provider defaults for this
+// conversation (an empty object restores the defaults)
+#[tauri::command]
+pub fn set_conversation_generation(app: AppHandle, conversation_id: String, generation: GenerationParams) -> Result<Conversation, AppError> {
+    let mut conversation = load_conversation_storage(&app, &conversation_id)?;
+    conversation.generation = generation;
+    save_conversation_storage(&app, &conversation)?;
+    Ok(conversation)
 }
 
+// Set the context policy for this conversation (None follows the
+// "context-policy" setting)
+#[tauri::command]
+pub fn set_conversation_context_policy(app: AppHandle, conversation_id: String, policy: Option<ContextPolicy>) -> Result<Conversation, AppError> {
+    let mut conversation = load_conversation_storage(&app, &conversation_id)?;
+    conversation.context_policy = policy;
+    save_conversation_storage(&app, &conversation)?;
+    Ok(conversation)
+}
+
+// Pin or unpin a message so it is always sent to the LLM
+#[tauri::command]
+pub fn set_message_pinned(app: AppHandle, conversation_id: String, message_index: usize, pinned: bool) -> Result<Conversation, AppError> {
+    let mut conversation = load_conversation_storage(&app, &conversation_id)?;
+    let message = conversation.messages
+        .get_mut(message_index)
+        .ok_or_else(|| AppError::not_found(format!("Message {} not found in conversation {}", message_index, conversation_id)))?;
+    message.pinned = pinned;
+    save_conversation_storage(&app, &conversation)?;
+    Ok(conversation)
+}
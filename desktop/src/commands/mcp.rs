@@ -0,0 +1,14 @@
+use tauri::{AppHandle, Manager};
+use crate::mcp::{McpServerStatus, McpServers};
+use crate::models::AppError;
+use crate::storage::settings::load_mcp_servers;
+
+// Connect the enabled MCP servers and list what each offers. `retry` also
+// retries servers that failed recently.
+#[tauri::command]
+pub async fn list_mcp_servers(app: AppHandle, retry: Option<bool>) -> Result<Vec<McpServerStatus>, AppError> {
+    let servers = load_mcp_servers(&app)?;
+    let state = app.state::<McpServers>();
+    state.sync(&app, &servers, retry.unwrap_or(false)).await;
+    Ok(state.statuses(&servers).await)
+}
@@ -1,17 +1,228 @@
 use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}};
 use std::collections::HashMap;
-use tauri::{AppHandle, Emitter, State};
+use tauri::{AppHandle, Emitter, Manager, State};
 use futures::future::AbortHandle;
-use crate::models::{Conversation, Message};
+use crate::models::{AppError, Conversation, ErrorKind, FileAttachment, ImageAttachment, Message};
 use crate::storage::conversations::{
     load_conversation as load_conversation_storage,
     save_conversation as save_conversation_storage,
     get_iso_timestamp,
 };
-use crate::api::providers::{call_llm, stream_llm};
+use crate::storage::settings::{find_persona, load_context_policy, load_reasoning_in_history};
+use crate::storage::attachments::load_message_images;
+use crate::api::providers::{call_llm, stream_llm, CallOptions};
+use crate::api::context::{message_text, select_messages, update_summary};
+use crate::api::tools::{load_tools, paired_tool_messages, ToolApprovals, ToolSet, MAX_TOOL_STEPS};
+use crate::models::ContextPolicy;
+use crate::api::stream::{StreamSink, StreamStatus};
+
+// Build the message list sent to the LLM and the call options for a
+// conversation. A persona's system prompt is prepended and its provider, model
+// and generation parameters apply, with the conversation's own generation
+// parameters taking precedence. The history is cut down by the conversation's
+// context policy, which may first update the conversation's summary. Earlier
+// answers' reasoning is only sent with the "reasoning-in-history" setting.
+async fn prepare_llm_call(app: &AppHandle, conversation: &mut Conversation) -> Result<(Vec<Message>, CallOptions), AppError> {
+    let persona = match &conversation.persona {
+        Some(name) => {
+            let persona = find_persona(app, name)?;
+            if persona.is_none() {
+                eprintln!("Warning: Persona {} no longer exists, continuing without it", name);
+            }
+            persona
+        }
+        None => None,
+    };
+    
+    let options = match &persona {
+        Some(persona) => CallOptions {
+            generation: persona.generation.merge(&conversation.generation),
+            provider: persona.provider.clone().filter(|p| !p.is_empty()),
+            model: persona.model.clone().filter(|m| !m.is_empty()),
+            ..Default::default()
+        },
+        None => CallOptions {
+            generation: conversation.generation.clone(),
+            ..Default::default()
+        },
+    };
+    
+    let policy = match &conversation.context_policy {
+        Some(policy) => policy.clone(),
+        None => load_context_policy(app)?,
+    };
+    
+    if let ContextPolicy::Summarize { keep_turns } = policy {
+        // A failed summary shouldn't block the message; the previous summary
+        // and the unsummarized turns are sent instead
+        if let Err(e) = update_summary(app, conversation, keep_turns, &options).await {
+            eprintln!("Warning: {}", e);
+        }
+    }
+    
+    let mut llm_messages: Vec<Message> = Vec::new();
+    
+    if let Some(persona) = &persona {
+        if !persona.system_prompt.trim().is_empty() {
+            llm_messages.push(Message {
+                role: "system".to_string(),
+                content: persona.system_prompt.clone(),
+                ..Default::default()
+            });
+        }
+    }
+    
+    let with_reasoning = load_reasoning_in_history(app)?;
+    let selected = paired_tool_messages(select_messages(conversation, &policy));
+    llm_messages.extend(selected.into_iter().map(|m| Message {
+        content: message_text(&m),
+        reasoning: if with_reasoning { m.reasoning } else { String::new() },
+        reasoning_signature: if with_reasoning { m.reasoning_signature } else { String::new() },
+        role: m.role,
+        timestamp: String::new(), // Not needed for LLM call
+        complete: true, // Not needed for LLM call
+        images: m.images,
+        tool_calls: m.tool_calls,
+        tool_call_id: m.tool_call_id,
+        tool_name: m.tool_name,
+        ..Default::default()
+    }));
+    
+    // Images are stored by reference; providers need their data
+    load_message_images(app, &mut llm_messages)?;
+    
+    Ok((llm_messages, options))
+}
+
+// Copy what a stream produced onto the assistant message
+fn apply_stream_result(message: &mut Message, sink: &StreamSink) {
+    message.content = sink.content.clone();
+    message.reasoning = sink.reasoning.clone();
+    message.reasoning_signature = sink.reasoning_signature.clone();
+    message.citations = sink.citations.clone();
+    message.usage = sink.usage.clone();
+    message.provider = sink.provider.clone();
+    message.model = sink.model.clone();
+}
+
+// After a failed or stopped stream keep what was streamed, or drop the empty
+// assistant message: providers reject empty assistant turns, and the
+// conversation can be retried from the user message
+fn finish_interrupted_message(app: &AppHandle, conversation_id: &str, sink: &StreamSink) -> Result<(), AppError> {
+    let mut conv = load_conversation_storage(app, conversation_id)?;
+    if !conv.messages.last().map(|m| m.role == "assistant" && !m.complete).unwrap_or(false) {
+        return Ok(());
+    }
+    
+    if sink.content.is_empty() && sink.reasoning.is_empty() {
+        conv.messages.pop();
+    } else if let Some(last_msg) = conv.messages.last_mut() {
+        apply_stream_result(last_msg, sink);
+        last_msg.complete = true; // Mark as complete even though interrupted
+    }
+    conv.updated_at = get_iso_timestamp();
+    save_conversation_storage(app, &conv)
+}
+
+// Stream the answer, running the tools the model calls. After each round of
+// calls the assistant message is completed with them, their results are saved
+// as "tool" messages and the model's next reply streams into a new assistant
+// message. The last round offers no tools, so the model has to answer.
+// Calls to tools that need approval wait for the user's answer. The reasoning
+// of each round is sent back with its calls, as Anthropic requires.
+async fn stream_with_tools(
+    app: &AppHandle,
+    conversation_id: &str,
+    mut llm_messages: Vec<Message>,
+    options: &CallOptions,
+    tools: &ToolSet,
+    sink: &mut StreamSink,
+) -> Result<(), AppError> {
+    let mut options = options.clone();
+    let mut citations = Vec::new(); // Sources tools returned, stored on the answer
+    
+    for step in 1..=MAX_TOOL_STEPS {
+        options.tools = if step < MAX_TOOL_STEPS { tools.specs() } else { Vec::new() };
+        stream_llm(app, &llm_messages, &options, sink).await?;
+        
+        let calls = sink.tool_calls();
+        if calls.is_empty() || sink.is_cancelled() {
+            return Ok(());
+        }
+        
+        // Save the calls before running them, so a stopped answer keeps them
+        let mut conversation = load_conversation_storage(app, conversation_id)?;
+        if let Some(last_msg) = conversation.messages.last_mut() {
+            if last_msg.role == "assistant" && !last_msg.complete {
+                apply_stream_result(last_msg, sink);
+                last_msg.tool_calls = calls.clone();
+                last_msg.complete = true;
+            }
+        }
+        conversation.updated_at = get_iso_timestamp();
+        save_conversation_storage(app, &conversation)?;
+        
+        llm_messages.push(Message {
+            role: "assistant".to_string(),
+            content: sink.content.clone(),
+            reasoning: sink.reasoning.clone(),
+            reasoning_signature: sink.reasoning_signature.clone(),
+            tool_calls: calls.clone(),
+            ..Default::default()
+        });
+        
+        for call in &calls {
+            let approved = !tools.needs_approval(call) || {
+                sink.status(StreamStatus::ToolApproval {
+                    name: call.name.clone(),
+                    arguments: call.arguments.clone(),
+                });
+                app.state::<ToolApprovals>().request(conversation_id).await
+            };
+            if sink.is_cancelled() {
+                return Ok(());
+            }
+            
+            let content = if approved {
+                sink.status(StreamStatus::ToolCall { name: call.name.clone() });
+                let output = tools.execute(app, call).await;
+                citations.extend(output.citations);
+                output.text
+            } else {
+                "The user declined to run this tool.".to_string()
+            };
+            let tool_msg = Message {
+                role: "tool".to_string(),
+                content,
+                timestamp: get_iso_timestamp(),
+                complete: true,
+                tool_call_id: call.id.clone(),
+                tool_name: call.name.clone(),
+                ..Default::default()
+            };
+            conversation.messages.push(tool_msg.clone());
+            llm_messages.push(tool_msg);
+        }
+        
+        conversation.messages.push(Message {
+            role: "assistant".to_string(),
+            timestamp: get_iso_timestamp(),
+            complete: false,
+            ..Default::default()
+        });
+        conversation.updated_at = get_iso_timestamp();
+        save_conversation_storage(app, &conversation)?;
+        
+        sink.reset();
+        sink.add_citations(citations.clone());
+        sink.status(StreamStatus::ToolStep);
+    }
+    
+    Ok(())
+}
 
 #[tauri::command]
-pub async fn send_message(app: AppHandle, conversation_id: String, user_message: String) -> Result<Conversation, String> {
+pub async fn send_message(app: AppHandle, conversation_id: String, user_message: String, images: Option<Vec<ImageAttachment>>, files: Option<Vec<FileAttachment>>) -> Result<Conversation, AppError> {
     // Load the conversation
     let mut conversation = load_conversation_storage(&app, &conversation_id)?;
     
@@ -30,31 +241,32 @@ pub async fn send_message(app: AppHandle, conversation_id: String, user_message:
             content: user_message,
             timestamp: user_timestamp,
             complete: true,
+            images: images.unwrap_or_default(),
+            files: files.unwrap_or_default(),
+            ..Default::default()
         };
         conversation.messages.push(user_msg);
     }
     
     // Prepare messages for LLM (extract role and content, exclude timestamp)
-    let llm_messages: Vec<Message> = conversation.messages
-        .iter()
-        .map(|m| Message {
-            role: m.role.clone(),
-            content: m.content.clone(),
-            timestamp: String::new(), // Not needed for LLM call
-            complete: true, // Not needed for LLM call
-        })
-        .collect();
+    let (llm_messages, options) = prepare_llm_call(&app, &mut conversation).await?;
     
     // Call LLM
-    let llm_response = call_llm(&app, &llm_messages).await?;
+    let completion = call_llm(&app, &llm_messages, &options).await?;
     
     // Add assistant message
     let assistant_timestamp = get_iso_timestamp();
     let assistant_msg = Message {
         role: "assistant".to_string(),
-        content: llm_response,
+        content: completion.content,
+        reasoning: completion.reasoning,
         timestamp: assistant_timestamp,
         complete: true,
+        citations: completion.citations,
+        usage: completion.usage,
+        provider: completion.provider,
+        model: completion.model,
+        ..Default::default()
     };
     conversation.messages.push(assistant_msg);
     
@@ -72,8 +284,10 @@ pub async fn send_message_stream(
     app: AppHandle,
     conversation_id: String,
     user_message: String,
+    images: Option<Vec<ImageAttachment>>,
+    files: Option<Vec<FileAttachment>>,
     abort_handles: State<'_, Arc<Mutex<HashMap<String, (AbortHandle, Arc<AtomicBool>)>>>>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     // Load the conversation
     let mut conversation = load_conversation_storage(&app, &conversation_id)?;
     
@@ -91,20 +305,16 @@ pub async fn send_message_stream(
             content: user_message,
             timestamp: user_timestamp,
             complete: true,
+            images: images.unwrap_or_default(),
+            files: files.unwrap_or_default(),
+            ..Default::default()
         };
         conversation.messages.push(user_msg);
     }
     
     // Prepare messages for LLM
-    let llm_messages: Vec<Message> = conversation.messages
-        .iter()
-        .map(|m| Message {
-            role: m.role.clone(),
-            content: m.content.clone(),
-            timestamp: String::new(),
-            complete: true, // Not needed for LLM call
-        })
-        .collect();
+    let (llm_messages, options) = prepare_llm_call(&app, &mut conversation).await?;
+    let tools = load_tools(&app).await?;
     
     // Create incomplete assistant message at the start
     let assistant_timestamp = get_iso_timestamp();
@@ -113,6 +323,7 @@ pub async fn send_message_stream(
         content: String::new(),
         timestamp: assistant_timestamp,
         complete: false,
+        ..Default::default()
     };
     conversation.messages.push(assistant_msg.clone());
     
@@ -131,27 +342,28 @@ pub async fn send_message_stream(
     }
     
     // Stream LLM response with periodic save callback
-    let mut full_response = String::new();
     let event_name = format!("stream-chunk-{}", conversation_id);
     let conversation_id_clone = conversation_id.clone();
     
     // Create callback for periodic saves
     let app_clone = app.clone();
-    let save_callback = Box::new(move |partial_content: &str| -> Result<(), String> {
+    let save_callback = Box::new(move |partial_content: &str, partial_reasoning: &str| -> Result<(), AppError> {
         // Load conversation, update assistant message, save
         let mut conv = load_conversation_storage(&app_clone, &conversation_id_clone)?;
         if let Some(last_msg) = conv.messages.last_mut() {
             if last_msg.role == "assistant" && !last_msg.complete {
                 last_msg.content = partial_content.to_string();
+                last_msg.reasoning = partial_reasoning.to_string();
                 conv.updated_at = get_iso_timestamp();
                 save_conversation_storage(&app_clone, &conv)?;
             }
         }
         Ok(())
     });
+    let mut sink = StreamSink::new(app.clone(), event_name.clone(), Some(save_callback), cancel_flag.clone());
     
-    // Wrap stream_llm in Abortable to handle cancellation
-    let stream_future = stream_llm(&app, &event_name, &llm_messages, &mut full_response, Some(save_callback), cancel_flag.clone());
+    // Wrap the stream in Abortable to handle cancellation
+    let stream_future = stream_with_tools(&app, &conversation_id, llm_messages, &options, &tools, &mut sink);
     let abortable_stream = futures::future::Abortable::new(stream_future, abort_registration);
     
     let stream_result = match abortable_stream.await {
@@ -160,21 +372,13 @@ pub async fn send_message_stream(
             if cancel_flag.load(Ordering::Relaxed) {
                 // Stream was aborted
                 // Save partial response
-                let mut conv = load_conversation_storage(&app, &conversation_id)?;
-                if let Some(last_msg) = conv.messages.last_mut() {
-                    if last_msg.role == "assistant" && !last_msg.complete {
-                        last_msg.content = full_response.clone();
-                        last_msg.complete = true; // Mark as complete even though aborted
-                        conv.updated_at = get_iso_timestamp();
-                        save_conversation_storage(&app, &conv)?;
-                    }
-                }
+                finish_interrupted_message(&app, &conversation_id, &sink)?;
                 // Emit cancellation event
                 app.emit(&event_name, "CANCELLED").map_err(|e| format!("Failed to emit cancellation: {}", e))?;
                 // Remove from abort handles
                 let mut handles = abort_handles.lock().map_err(|e| format!("Failed to lock abort handles: {}", e))?;
                 handles.remove(&conversation_id);
-                return Err("Stream cancelled by user".to_string());
+                return Err(AppError::new(ErrorKind::Cancelled, "Stream cancelled by user"));
             }
             // Normal completion
             Ok(())
@@ -186,21 +390,13 @@ pub async fn send_message_stream(
         Err(_) => {
             // Stream was aborted by Abortable
             // Save partial response
-            let mut conv = load_conversation_storage(&app, &conversation_id)?;
-            if let Some(last_msg) = conv.messages.last_mut() {
-                if last_msg.role == "assistant" && !last_msg.complete {
-                    last_msg.content = full_response.clone();
-                    last_msg.complete = true; // Mark as complete even though aborted
-                    conv.updated_at = get_iso_timestamp();
-                    save_conversation_storage(&app, &conv)?;
-                }
-            }
+            finish_interrupted_message(&app, &conversation_id, &sink)?;
             // Emit cancellation event
             app.emit(&event_name, "CANCELLED").map_err(|e| format!("Failed to emit cancellation: {}", e))?;
             // Remove from abort handles
             let mut handles = abort_handles.lock().map_err(|e| format!("Failed to lock abort handles: {}", e))?;
             handles.remove(&conversation_id);
-            return Err("Stream cancelled by user".to_string());
+            return Err(AppError::new(ErrorKind::Cancelled, "Stream cancelled by user"));
         }
     };
     
@@ -210,6 +406,10 @@ pub async fn send_message_stream(
         handles.remove(&conversation_id);
     }
     
+    if stream_result.is_err() {
+        finish_interrupted_message(&app, &conversation_id, &sink)?;
+    }
+    
     stream_result?;
     
     // Send completion event
@@ -219,7 +419,7 @@ pub async fn send_message_stream(
     let mut conversation = load_conversation_storage(&app, &conversation_id)?;
     if let Some(last_msg) = conversation.messages.last_mut() {
         if last_msg.role == "assistant" {
-            last_msg.content = full_response;
+            apply_stream_result(last_msg, &sink);
             last_msg.complete = true;
         }
     }
@@ -237,15 +437,22 @@ pub async fn send_message_stream(
 pub async fn stop_message_stream(
     conversation_id: String,
     abort_handles: State<'_, Arc<Mutex<HashMap<String, (AbortHandle, Arc<AtomicBool>)>>>>,
-) -> Result<(), String> {
+    approvals: State<'_, ToolApprovals>,
+) -> Result<(), AppError> {
     let mut handles = abort_handles.lock().map_err(|e| format!("Failed to lock abort handles: {}", e))?;
     
     if let Some((handle, flag)) = handles.remove(&conversation_id) {
         flag.store(true, Ordering::Relaxed);
         handle.abort();
+        approvals.cancel(&conversation_id);
         Ok(())
     } else {
-        Err("No active stream found for this conversation".to_string())
+        Err(AppError::not_found("No active stream found for this conversation"))
     }
 }
 
+// Allow or deny the tool call a streaming answer is waiting on
+#[tauri::command]
+pub fn answer_tool_approval(conversation_id: String, approved: bool, approvals: State<'_, ToolApprovals>) -> Result<(), AppError> {
+    approvals.answer(&conversation_id, approved)
+}
@@ -1,8 +1,14 @@
 pub mod settings;
 pub mod conversations;
 pub mod messages;
+pub mod usage;
+pub mod attachments;
+pub mod mcp;
 
 pub use settings::*;
 pub use conversations::*;
 pub use messages::*;
+pub use usage::*;
+pub use attachments::*;
+pub use mcp::*;
 
@@ -1,16 +1,20 @@
 use std::collections::HashMap;
-use tauri::AppHandle;
-use serde::{Deserialize, Serialize};
-use crate::storage::settings::{load_settings as load_settings_storage, save_settings as save_settings_storage};
+use std::fs;
+use tauri::{AppHandle, Manager};
+use crate::storage::settings::{load_settings as load_settings_storage, save_settings as save_settings_storage, load_mcp_servers, load_personas, save_personas};
 use crate::window::shortcuts::update_shortcut;
+use crate::api::catalog::{self, ModelCatalog};
+use crate::api::http::reload_http_client;
+use crate::mcp::McpServers;
+use crate::models::{AppError, ModelInfo, Persona};
 
 #[tauri::command]
-pub fn load_settings(app: AppHandle) -> Result<HashMap<String, serde_json::Value>, String> {
+pub fn load_settings(app: AppHandle) -> Result<HashMap<String, serde_json::Value>, AppError> {
     load_settings_storage(&app)
 }
 
 #[tauri::command]
-pub fn save_settings(app: AppHandle, settings_map: HashMap<String, serde_json::Value>) -> Result<(), String> {
+pub fn save_settings(app: AppHandle, settings_map: HashMap<String, serde_json::Value>) -> Result<(), AppError> {
     // Get the current shortcut before trying to update
     let current_settings = load_settings_storage(&app).unwrap_or_default();
     let previous_shortcut = current_settings
@@ -33,53 +37,80 @@ pub fn save_settings(app: AppHandle, settings_map: HashMap<String, serde_json::V
                 eprintln!("Warning: Failed to revert shortcut: {}", revert_err);
             }
             // Return error so frontend can show alert
-            return Err(format!("Failed to set keyboard shortcut: {}. Reverted to previous shortcut.", e));
+            return Err(AppError::config(format!("Failed to set keyboard shortcut: {}. Reverted to previous shortcut.", e)));
         }
     }
     
     // Save settings
     save_settings_storage(&app, settings_map)?;
     
+    // URLs or API keys may have changed
+    app.state::<ModelCatalog>().clear();
+    
+    // Stop MCP servers that were removed, disabled or changed; the others
+    // connect when tools are next loaded
+    let servers = load_mcp_servers(&app)?;
+    let app_handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        app_handle.state::<McpServers>().close_stale(&servers).await;
+    });
+    
+    // The previous client stays in use if the new network settings are invalid
+    reload_http_client(&app).map_err(|e| AppError::config(format!("Settings saved, but the network settings were not applied: {}", e)))?;
+    
     Ok(())
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaModel {
-    name: String,
+// List the models of a provider setting ("ollama", "openai-compatible:groq", ...)
+#[tauri::command]
+pub async fn list_models(app: AppHandle, provider: String, refresh: Option<bool>) -> Result<Vec<ModelInfo>, AppError> {
+    catalog::list_models(&app, &provider, refresh.unwrap_or(false)).await
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct OllamaTagsResponse {
-    models: Vec<OllamaModel>,
+// Write personas to a JSON file (all of them unless `names` is given)
+#[tauri::command]
+pub fn export_personas(app: AppHandle, path: String, names: Option<Vec<String>>) -> Result<(), AppError> {
+    let personas: Vec<Persona> = load_personas(&app)?
+        .into_iter()
+        .filter(|p| names.as_ref().map(|names| names.contains(&p.name)).unwrap_or(true))
+        .collect();
+    
+    let json = serde_json::to_string_pretty(&personas)
+        .map_err(|e| format!("Failed to serialize personas: {}", e))?;
+    
+    fs::write(&path, json)
+        .map_err(|e| AppError::storage(format!("Failed to write {}: {}", path, e)))
 }
 
+// Read personas from a JSON file holding one persona or a list of them.
+// Imported personas replace existing ones with the same name.
 #[tauri::command]
-pub async fn list_ollama_models(url: String) -> Result<Vec<String>, String> {
-    let client = reqwest::Client::new();
-    let api_url = format!("{}/api/tags", url.trim_end_matches('/'));
+pub fn import_personas(app: AppHandle, path: String) -> Result<Vec<Persona>, AppError> {
+    let content = fs::read_to_string(&path)
+        .map_err(|e| AppError::storage(format!("Failed to read {}: {}", path, e)))?;
     
-    let response = client
-        .get(&api_url)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to connect to Ollama at {}: {}", api_url, e))?;
+    let value: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|e| AppError::corrupt(format!("Failed to parse {}: {}", path, e)))?;
     
-    let status = response.status();
-    if !status.is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Ollama API error ({}): {}", status, error_text));
+    let imported: Vec<Persona> = if value.is_array() {
+        serde_json::from_value(value)
+    } else {
+        serde_json::from_value(value).map(|persona| vec![persona])
     }
+    .map_err(|e| AppError::corrupt(format!("Invalid persona file {}: {}", path, e)))?;
     
-    let tags_response: OllamaTagsResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
+    if imported.iter().any(|p| p.name.trim().is_empty()) {
+        return Err(AppError::corrupt("Every persona needs a name"));
+    }
     
-    let model_names: Vec<String> = tags_response.models
-        .into_iter()
-        .map(|m| m.name)
-        .collect();
+    let mut personas = load_personas(&app)?;
+    for persona in &imported {
+        match personas.iter_mut().find(|p| p.name == persona.name) {
+            Some(existing) => *existing = persona.clone(),
+            None => personas.push(persona.clone()),
+        }
+    }
+    save_personas(&app, &personas)?;
     
-    Ok(model_names)
+    Ok(imported)
 }
-
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use tauri::AppHandle;
+use crate::models::{AppError, TokenUsage, UsageGroup, UsageSummary, UsageTotals};
+use crate::storage::conversations::list_conversations as list_conversations_storage;
+
+// Accumulates totals per key, remembering a display label for each
+#[derive(Default)]
+struct Grouping {
+    groups: BTreeMap<String, UsageGroup>,
+}
+
+impl Grouping {
+    fn add(&mut self, key: &str, label: &str, usage: &TokenUsage) {
+        self.groups
+            .entry(key.to_string())
+            .or_insert_with(|| UsageGroup {
+                key: key.to_string(),
+                label: label.to_string(),
+                totals: UsageTotals::default(),
+            })
+            .totals
+            .add(usage);
+    }
+    
+    // Most expensive first, then by token count
+    fn by_cost(self) -> Vec<UsageGroup> {
+        let mut groups: Vec<UsageGroup> = self.groups.into_values().collect();
+        groups.sort_by(|a, b| {
+            b.totals.cost
+                .total_cmp(&a.totals.cost)
+                .then_with(|| {
+                    let a_tokens = a.totals.prompt_tokens + a.totals.completion_tokens;
+                    let b_tokens = b.totals.prompt_tokens + b.totals.completion_tokens;
+                    b_tokens.cmp(&a_tokens)
+                })
+        });
+        groups
+    }
+    
+    // Keys in ascending order (used for days)
+    fn by_key(self) -> Vec<UsageGroup> {
+        self.groups.into_values().collect()
+    }
+}
+
+// Aggregate token usage and estimated cost of assistant responses and
+// context summaries per conversation, day (UTC), provider and model. `since` and `until` are
+// inclusive dates (YYYY-MM-DD).
+#[tauri::command]
+pub fn get_usage_summary(app: AppHandle, since: Option<String>, until: Option<String>) -> Result<UsageSummary, AppError> {
+    let conversations = list_conversations_storage(&app)?;
+    
+    let mut total = UsageTotals::default();
+    let mut by_conversation = Grouping::default();
+    let mut by_day = Grouping::default();
+    let mut by_provider = Grouping::default();
+    let mut by_model = Grouping::default();
+    
+    for conversation in &conversations {
+        // Answers, then requests that produced no message (summaries)
+        let answers = conversation
+            .messages
+            .iter()
+            .filter_map(|m| m.usage.as_ref().map(|usage| (&m.timestamp, &m.provider, &m.model, usage)));
+        let others = conversation
+            .extra_usage
+            .iter()
+            .map(|r| (&r.timestamp, &r.provider, &r.model, &r.usage));
+        
+        for (timestamp, provider, model, usage) in answers.chain(others) {
+            // Timestamps are "YYYY-MM-DDTHH:MM:SSZ"
+            let day = timestamp.get(..10).unwrap_or(timestamp);
+            if since.as_deref().map(|since| day < since).unwrap_or(false)
+                || until.as_deref().map(|until| day > until).unwrap_or(false)
+            {
+                continue;
+            }
+            
+            total.add(usage);
+            by_conversation.add(&conversation.id, &conversation.title, usage);
+            by_day.add(day, day, usage);
+            by_provider.add(provider, provider, usage);
+            by_model.add(model, model, usage);
+        }
+    }
+    
+    Ok(UsageSummary {
+        total,
+        by_conversation: by_conversation.by_cost(),
+        by_day: by_day.by_key(),
+        by_provider: by_provider.by_cost(),
+        by_model: by_model.by_cost(),
+    })
+}
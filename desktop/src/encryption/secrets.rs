@@ -115,6 +115,12 @@ pub fn save_secrets(app: &AppHandle, secrets: &HashMap<String, String>) -> Resul
     Ok(())
 }
 
+// Settings fields kept in encrypted storage: API keys and other fields ending
+// in `_key`, and every value of a `secret-headers` or `secret-env` map
+pub fn is_secret_field(path_prefix: &str, key: &str) -> bool {
+    key.ends_with("_key") || matches!(path_prefix.rsplit('.').next(), Some("secret-headers" | "secret-env"))
+}
+
 // Get API key from encrypted storage
 pub fn get_api_key_from_encrypted(app: &AppHandle, path: &str) -> Result<String, String> {
     let secrets = load_secrets(app)?;
@@ -149,8 +155,8 @@ pub fn load_api_keys_from_encrypted(app: &AppHandle, value: &mut serde_json::Val
                     format!("{}.{}", path_prefix, key)
                 };
                 
-                // Check if this is an API key field (ends with _key)
-                if key.ends_with("_key") {
+                // Check if this is an API key or other secret field
+                if is_secret_field(path_prefix, key) {
                     if let serde_json::Value::String(s) = val {
                         // If marker, try to load from encrypted storage
                         if s == "__NOT_STORED_HERE__" {
@@ -180,8 +186,8 @@ pub fn save_api_keys_to_encrypted(app: &AppHandle, value: &mut serde_json::Value
                     format!("{}.{}", path_prefix, key)
                 };
                 
-                // Check if this is an API key field (ends with _key)
-                if key.ends_with("_key") {
+                // Check if this is an API key or other secret field
+                if is_secret_field(path_prefix, key) {
                     if let serde_json::Value::String(s) = val {
                         if !s.is_empty() && s != "__NOT_STORED_HERE__" {
                             // Store in encrypted storage
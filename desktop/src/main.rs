@@ -3,6 +3,7 @@ mod encryption;
 mod storage;
 mod api;
 mod commands;
+mod mcp;
 mod window;
 
 use std::sync::{Arc, Mutex, atomic::AtomicBool};
@@ -14,6 +15,10 @@ use futures::future::AbortHandle;
 
 use window::shortcuts::{toggle_window, parse_shortcut, set_shortcut_listening_mode, update_shortcut};
 use storage::settings::load_settings as load_settings_storage;
+use api::catalog::ModelCatalog;
+use api::http::{reload_http_client, HttpClient};
+use api::tools::ToolApprovals;
+use mcp::McpServers;
 use commands::{
     load_settings,
     save_settings,
@@ -24,10 +29,21 @@ use commands::{
     delete_all_conversations,
     get_conversation_history,
     create_conversation,
+    set_conversation_generation,
+    set_conversation_context_policy,
+    set_message_pinned,
     send_message,
     send_message_stream,
     stop_message_stream,
-    list_ollama_models,
+    list_models,
+    export_personas,
+    import_personas,
+    get_usage_summary,
+    attach_image,
+    load_image,
+    attach_file,
+    list_mcp_servers,
+    answer_tool_approval,
 };
 
 fn main() {
@@ -43,6 +59,10 @@ fn main() {
     tauri::Builder::default()
         .manage(is_listening)
         .manage(abort_handles)
+        .manage(ModelCatalog::default())
+        .manage(HttpClient::default())
+        .manage(McpServers::default())
+        .manage(ToolApprovals::default())
         .plugin(tauri_plugin_window_state::Builder::default().build())
         .plugin(tauri_plugin_decorum::init())
         .plugin(
@@ -64,12 +84,23 @@ fn main() {
             delete_all_conversations,
             get_conversation_history,
             create_conversation,
+            set_conversation_generation,
+            set_conversation_context_policy,
+            set_message_pinned,
             send_message,
             send_message_stream,
             stop_message_stream,
             update_shortcut,
             set_shortcut_listening_mode,
-            list_ollama_models
+            list_models,
+            export_personas,
+            import_personas,
+            get_usage_summary,
+            attach_image,
+            load_image,
+            attach_file,
+            list_mcp_servers,
+            answer_tool_approval
         ])
         .setup(|app| {
             let window = app.get_webview_window("main").unwrap();
@@ -89,6 +120,11 @@ fn main() {
             
             let app_handle = app.handle();
             
+            // Apply the saved proxy, timeout and certificate settings
+            if let Err(e) = reload_http_client(&app_handle) {
+                eprintln!("Warning: Failed to apply network settings: {}", e);
+            }
+            
             // Load settings and register keyboard shortcut
             let settings_result = load_settings_storage(&app_handle);
             let shortcut_str = if let Ok(settings) = settings_result {
@@ -137,5 +173,10 @@ fn main() {
                 // Handle dock icon click - toggle window visibility
                 toggle_window(&app_handle, &is_listening_for_run);
             }
+            
+            // Stop the MCP servers running as child processes
+            if let RunEvent::Exit = event {
+                tauri::async_runtime::block_on(app_handle.state::<McpServers>().shutdown());
+            }
         });
 }
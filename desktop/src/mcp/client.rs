@@ -0,0 +1,253 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tauri::{AppHandle, Manager};
+
+use crate::api::http::HttpClient;
+use crate::mcp::transport::{HttpTransport, StdioTransport, Transport};
+use crate::models::{AppError, ErrorKind, McpServer, McpTransport};
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30); // Covers servers installed on first launch (npx, uvx)
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+const MAX_PAGES: usize = 50; // Guards against a server returning the same cursor forever
+
+// A tool offered by a server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpToolInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "inputSchema", default)]
+    pub input_schema: Value,
+}
+
+// A resource a server can read, listed for the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpResourceInfo {
+    pub uri: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "mimeType", default)]
+    pub mime_type: String,
+}
+
+// A prompt template a server provides, listed for the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPromptInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub arguments: Vec<McpPromptArgument>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct McpPromptArgument {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub required: bool,
+}
+
+// A session with one MCP server. What the server offers is listed once when
+// connecting.
+pub struct McpClient {
+    name: String,
+    transport: Box<dyn Transport>,
+    next_id: AtomicU64,
+    pub server_info: String, // Name and version the server reported
+    pub tools: Vec<McpToolInfo>,
+    pub resources: Vec<McpResourceInfo>,
+    pub prompts: Vec<McpPromptInfo>,
+}
+
+impl McpClient {
+    // Start or reach the server, negotiate the protocol and list its tools,
+    // resources and prompts
+    pub async fn connect(app: &AppHandle, name: &str, server: &McpServer) -> Result<Self, AppError> {
+        let transport: Box<dyn Transport> = match server.transport {
+            McpTransport::Stdio => {
+                let mut env = server.env.clone();
+                env.extend(server.secret_env.clone());
+                Box::new(StdioTransport::spawn(name, &server.command, &server.args, &env)?)
+            }
+            McpTransport::Http => {
+                let client = app.state::<HttpClient>().get();
+                Box::new(HttpTransport::new(name, client, &server.url, &server.http)?)
+            }
+        };
+        
+        let mut client = Self {
+            name: name.to_string(),
+            transport,
+            next_id: AtomicU64::new(1),
+            server_info: String::new(),
+            tools: Vec::new(),
+            resources: Vec::new(),
+            prompts: Vec::new(),
+        };
+        
+        // Don't leave a child process behind when the handshake fails
+        if let Err(e) = client.initialize().await {
+            client.close().await;
+            return Err(e);
+        }
+        Ok(client)
+    }
+    
+    async fn initialize(&mut self) -> Result<(), AppError> {
+        let result = self
+            .request(
+                "initialize",
+                json!({
+                    "protocolVersion": PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": { "name": "Fast Chat", "version": env!("CARGO_PKG_VERSION") }
+                }),
+                CONNECT_TIMEOUT,
+            )
+            .await?;
+        
+        let version = result
+            .get("protocolVersion")
+            .and_then(|v| v.as_str())
+            .unwrap_or(PROTOCOL_VERSION);
+        self.transport.set_protocol_version(version);
+        
+        if let Some(info) = result.get("serverInfo") {
+            let server_name = info.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+            let server_version = info.get("version").and_then(|v| v.as_str()).unwrap_or_default();
+            self.server_info = format!("{} {}", server_name, server_version).trim().to_string();
+        }
+        
+        self.transport
+            .notify(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }))
+            .await?;
+        
+        // Only list what the server advertises
+        let capabilities = result.get("capabilities").cloned().unwrap_or_default();
+        if capabilities.get("tools").is_some() {
+            self.tools = self.list("tools/list", "tools").await?;
+        }
+        if capabilities.get("resources").is_some() {
+            self.resources = self.list("resources/list", "resources").await?;
+        }
+        if capabilities.get("prompts").is_some() {
+            self.prompts = self.list("prompts/list", "prompts").await?;
+        }
+        
+        Ok(())
+    }
+    
+    // Send a request and return its result, turning a JSON-RPC error into an AppError
+    async fn request(&self, method: &str, params: Value, timeout: Duration) -> Result<Value, AppError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        
+        let response = tokio::time::timeout(timeout, self.transport.request(id, message))
+            .await
+            .map_err(|_| AppError::new(
+                ErrorKind::Timeout,
+                format!("MCP server {} didn't answer {} within {} s", self.name, method, timeout.as_secs()),
+            ))??;
+        
+        if let Some(error) = response.get("error") {
+            let message = error.get("message").and_then(|m| m.as_str()).unwrap_or("unknown error");
+            return Err(AppError::new(ErrorKind::InvalidRequest, format!("MCP server {}: {}", self.name, message)));
+        }
+        
+        Ok(response.get("result").cloned().unwrap_or_default())
+    }
+    
+    // Collect every page of a list method
+    async fn list<T: DeserializeOwned>(&self, method: &str, key: &str) -> Result<Vec<T>, AppError> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        
+        for _ in 0..MAX_PAGES {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self.request(method, params, REQUEST_TIMEOUT).await?;
+            
+            if let Some(page) = result.get(key) {
+                let page: Vec<T> = serde_json::from_value(page.clone()).map_err(|e| {
+                    AppError::invalid_response(format!("Invalid {} from MCP server {}: {}", method, self.name, e))
+                })?;
+                items.extend(page);
+            }
+            
+            cursor = result.get("nextCursor").and_then(|c| c.as_str()).map(|c| c.to_string());
+            if cursor.is_none() {
+                break;
+            }
+        }
+        
+        Ok(items)
+    }
+    
+    // Call a tool and return its text content. A result the server flags as
+    // an error is returned as Err so the model sees it as one.
+    pub async fn call_tool(&self, name: &str, arguments: &Value) -> Result<String, AppError> {
+        let result = self
+            .request("tools/call", json!({ "name": name, "arguments": arguments }), REQUEST_TIMEOUT)
+            .await?;
+        
+        let text = tool_result_text(&result);
+        if result.get("isError").and_then(|e| e.as_bool()).unwrap_or(false) {
+            return Err(AppError::new(ErrorKind::InvalidRequest, text));
+        }
+        Ok(text)
+    }
+    
+    pub fn is_alive(&self) -> bool {
+        self.transport.is_alive()
+    }
+    
+    pub async fn close(&self) {
+        self.transport.close().await;
+    }
+}
+
+// Text of a tool result: text blocks as they are, other content described
+// in brackets, and structured content when there is nothing else
+fn tool_result_text(result: &Value) -> String {
+    let blocks = result
+        .get("content")
+        .and_then(|c| c.as_array())
+        .cloned()
+        .unwrap_or_default();
+    
+    let parts: Vec<String> = blocks
+        .iter()
+        .map(|block| {
+            let field = |name: &str| block.get(name).and_then(|v| v.as_str()).unwrap_or_default();
+            match field("type") {
+                "text" => field("text").to_string(),
+                "image" => format!("[Image: {}]", field("mimeType")),
+                "audio" => format!("[Audio: {}]", field("mimeType")),
+                "resource" => {
+                    let resource = &block["resource"];
+                    match resource.get("text").and_then(|t| t.as_str()) {
+                        Some(text) => text.to_string(),
+                        None => format!("[Resource: {}]", resource["uri"].as_str().unwrap_or_default()),
+                    }
+                }
+                "resource_link" => format!("[Resource: {}]", field("uri")),
+                other => format!("[Unsupported content: {}]", other),
+            }
+        })
+        .collect();
+    
+    match result.get("structuredContent") {
+        Some(structured) if parts.is_empty() => structured.to_string(),
+        _ => parts.join("\n"),
+    }
+}
@@ -0,0 +1,281 @@
+// Model Context Protocol client: servers configured in settings are started
+// (stdio) or reached (streamable HTTP) when tools are loaded, and their tools
+// are offered to the model next to the built-in ones
+pub mod client;
+pub mod transport;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use async_trait::async_trait;
+use futures::future::join_all;
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tokio::sync::Mutex;
+
+use crate::api::tools::{Tool, ToolOutput, ToolSpec};
+use crate::mcp::client::{McpClient, McpPromptInfo, McpResourceInfo, McpToolInfo};
+use crate::models::{AppError, McpServer, ToolApproval};
+use crate::storage::settings::load_mcp_servers;
+
+// A server that failed to connect isn't retried on every message; changing
+// its settings or checking the servers retries at once
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+
+const MAX_TOOL_NAME: usize = 64; // Longest function name OpenAI and Anthropic accept
+
+struct Connection {
+    config: McpServer,
+    client: Arc<McpClient>,
+}
+
+struct Failure {
+    config: McpServer,
+    at: Instant,
+    error: String,
+}
+
+#[derive(Default)]
+struct McpState {
+    connections: HashMap<String, Connection>,
+    failures: HashMap<String, Failure>,
+}
+
+// Connected MCP servers, kept in Tauri state. A server is reconnected when
+// its settings change and closed when it is removed or disabled; all of them
+// are closed when the app exits.
+#[derive(Default)]
+pub struct McpServers {
+    state: Mutex<McpState>,
+}
+
+// Whether a connection made with `a` still serves `b`: tool approvals are read
+// from the settings on every call, so changing them doesn't restart the server
+fn same_connection(a: &McpServer, b: &McpServer) -> bool {
+    let mut a = a.clone();
+    a.tool_approval = b.tool_approval.clone();
+    a == *b
+}
+
+// Close connections whose server was removed, disabled or changed, and drop
+// those whose server exited so the next sync starts it again
+async fn close_stale(state: &mut McpState, servers: &BTreeMap<String, McpServer>) {
+    let stale: Vec<String> = state
+        .connections
+        .iter()
+        .filter(|(name, connection)| match servers.get(*name) {
+            Some(server) => {
+                !server.enabled || !same_connection(&connection.config, server) || !connection.client.is_alive()
+            }
+            None => true,
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    
+    for name in stale {
+        if let Some(connection) = state.connections.remove(&name) {
+            connection.client.close().await;
+        }
+    }
+}
+
+impl McpServers {
+    // Bring the connections in line with the settings: close stale ones and
+    // connect every enabled server not connected yet. `retry` also retries
+    // servers that failed recently.
+    pub async fn sync(&self, app: &AppHandle, servers: &BTreeMap<String, McpServer>, retry: bool) {
+        let mut state = self.state.lock().await;
+        close_stale(&mut state, servers).await;
+        
+        state.failures.retain(|name, failure| {
+            !retry
+                && failure.at.elapsed() < RETRY_DELAY
+                && servers.get(name).map(|s| same_connection(&failure.config, s)).unwrap_or(false)
+        });
+        
+        let missing: Vec<(&String, &McpServer)> = servers
+            .iter()
+            .filter(|(name, server)| {
+                server.enabled && !state.connections.contains_key(*name) && !state.failures.contains_key(*name)
+            })
+            .collect();
+        
+        let results = join_all(missing.into_iter().map(|(name, server)| async move {
+            (name, server, McpClient::connect(app, name, server).await)
+        }))
+        .await;
+        
+        for (name, server, result) in results {
+            match result {
+                Ok(client) => {
+                    state.connections.insert(name.clone(), Connection {
+                        config: server.clone(),
+                        client: Arc::new(client),
+                    });
+                }
+                Err(e) => {
+                    eprintln!("Warning: Failed to connect to MCP server {}: {}", name, e);
+                    state.failures.insert(name.clone(), Failure {
+                        config: server.clone(),
+                        at: Instant::now(),
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+    }
+    
+    // Close the connections the settings no longer match, without connecting
+    // anything, so removed or disabled servers stop when settings are saved
+    pub async fn close_stale(&self, servers: &BTreeMap<String, McpServer>) {
+        let mut state = self.state.lock().await;
+        close_stale(&mut state, servers).await;
+    }
+    
+    // Close every connection, stopping the servers' child processes
+    pub async fn shutdown(&self) {
+        let mut state = self.state.lock().await;
+        let connections: Vec<Connection> = state.connections.drain().map(|(_, c)| c).collect();
+        join_all(connections.iter().map(|connection| connection.client.close())).await;
+    }
+    
+    // Tools of the connected servers, except those denied in the settings
+    pub async fn tools(&self, servers: &BTreeMap<String, McpServer>) -> Vec<Arc<dyn Tool>> {
+        let state = self.state.lock().await;
+        let mut tools: Vec<Arc<dyn Tool>> = Vec::new();
+        
+        for (name, connection) in &state.connections {
+            let approvals = servers.get(name).map(|s| &s.tool_approval);
+            for info in &connection.client.tools {
+                let approval = approvals
+                    .and_then(|a| a.get(&info.name))
+                    .copied()
+                    .unwrap_or_default();
+                if approval == ToolApproval::Deny {
+                    continue;
+                }
+                tools.push(Arc::new(McpTool {
+                    server: name.clone(),
+                    client: connection.client.clone(),
+                    info: info.clone(),
+                    approval,
+                }));
+            }
+        }
+        
+        tools
+    }
+    
+    pub async fn statuses(&self, servers: &BTreeMap<String, McpServer>) -> Vec<McpServerStatus> {
+        let state = self.state.lock().await;
+        
+        servers
+            .iter()
+            .map(|(name, server)| {
+                let connection = state.connections.get(name).filter(|c| c.client.is_alive());
+                let client = connection.map(|c| &c.client);
+                McpServerStatus {
+                    name: name.clone(),
+                    enabled: server.enabled,
+                    connected: connection.is_some(),
+                    error: state.failures.get(name).map(|f| f.error.clone()),
+                    server_info: client.map(|c| c.server_info.clone()).unwrap_or_default(),
+                    tools: client
+                        .map(|c| {
+                            c.tools
+                                .iter()
+                                .map(|tool| McpToolStatus {
+                                    name: tool.name.clone(),
+                                    description: tool.description.clone(),
+                                    approval: server.tool_approval.get(&tool.name).copied().unwrap_or_default(),
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    resources: client.map(|c| c.resources.clone()).unwrap_or_default(),
+                    prompts: client.map(|c| c.prompts.clone()).unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+// A configured server and what it offers, shown in settings
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpServerStatus {
+    pub name: String,
+    pub enabled: bool,
+    pub connected: bool,
+    pub error: Option<String>, // Why the last connection attempt failed
+    pub server_info: String,
+    pub tools: Vec<McpToolStatus>,
+    pub resources: Vec<McpResourceInfo>,
+    pub prompts: Vec<McpPromptInfo>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct McpToolStatus {
+    pub name: String,
+    pub description: String,
+    pub approval: ToolApproval,
+}
+
+// Tools of the enabled MCP servers, connecting the ones not connected yet.
+// A server that can't be reached is left out rather than failing the message.
+pub async fn load_mcp_tools(app: &AppHandle) -> Result<Vec<Arc<dyn Tool>>, AppError> {
+    let servers = load_mcp_servers(app)?;
+    let state = app.state::<McpServers>();
+    state.sync(app, &servers, false).await;
+    Ok(state.tools(&servers).await)
+}
+
+// Name the model sees: the tool's name prefixed by its server's, since two
+// servers may offer tools with the same name, limited to the characters
+// every provider accepts
+fn tool_name(server: &str, tool: &str) -> String {
+    format!("{}_{}", server, tool)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(MAX_TOOL_NAME)
+        .collect()
+}
+
+// A tool of an MCP server, run by the server
+struct McpTool {
+    server: String,
+    client: Arc<McpClient>,
+    info: McpToolInfo,
+    approval: ToolApproval,
+}
+
+#[async_trait]
+impl Tool for McpTool {
+    fn spec(&self) -> ToolSpec {
+        let description = if self.info.description.is_empty() {
+            format!("{} tool of the {} server", self.info.name, self.server)
+        } else {
+            self.info.description.clone()
+        };
+        let parameters = if self.info.input_schema.is_object() {
+            self.info.input_schema.clone()
+        } else {
+            serde_json::json!({ "type": "object", "properties": {} })
+        };
+        
+        ToolSpec {
+            name: tool_name(&self.server, &self.info.name),
+            description,
+            parameters,
+        }
+    }
+    
+    fn needs_approval(&self) -> bool {
+        self.approval == ToolApproval::Ask
+    }
+    
+    async fn execute(&self, _app: &AppHandle, arguments: &serde_json::Value) -> Result<ToolOutput, AppError> {
+        self.client.call_tool(&self.info.name, arguments).await.map(ToolOutput::from)
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command};
+use tokio::sync::{oneshot, Mutex as AsyncMutex};
+
+use crate::api::decode::{SseDecoder, StreamDecoder};
+use crate::api::http::{apply_http_options, error_response, request_error};
+use crate::models::{AppError, ErrorKind, HttpOptions};
+
+// How long a stdio server gets to exit once its input is closed before it is killed
+const EXIT_GRACE: Duration = Duration::from_secs(2);
+
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+type Pending = Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value, AppError>>>>>;
+
+// The guarded values stay consistent even if a holder panicked
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Carries JSON-RPC messages between the client and an MCP server
+#[async_trait]
+pub trait Transport: Send + Sync {
+    // Send a request and wait for the message answering it
+    async fn request(&self, id: u64, message: Value) -> Result<Value, AppError>;
+    
+    // Send a notification, which gets no answer
+    async fn notify(&self, message: Value) -> Result<(), AppError>;
+    
+    // Protocol version agreed on by `initialize`, for transports that send it
+    // with every request
+    fn set_protocol_version(&self, _version: &str) {}
+    
+    // Whether the server can still answer; a stdio server that exited can't
+    fn is_alive(&self) -> bool {
+        true
+    }
+    
+    async fn close(&self);
+}
+
+// Removes a request from the pending map when it is answered, fails or is
+// abandoned, e.g. when the caller's timeout drops it
+struct PendingEntry<'a> {
+    pending: &'a Pending,
+    id: u64,
+}
+
+impl Drop for PendingEntry<'_> {
+    fn drop(&mut self) {
+        lock(self.pending).remove(&self.id);
+    }
+}
+
+// A server launched as a child process, exchanging one JSON message per line
+// on its stdin and stdout. Its stderr is passed to the app's log.
+pub struct StdioTransport {
+    name: String,
+    child: AsyncMutex<Child>,
+    stdin: Arc<AsyncMutex<Option<ChildStdin>>>,
+    pending: Pending,
+    exited: Arc<AtomicBool>,
+}
+
+impl StdioTransport {
+    pub fn spawn(name: &str, program: &str, args: &[String], env: &HashMap<String, String>) -> Result<Self, AppError> {
+        if program.trim().is_empty() {
+            return Err(AppError::config(format!("MCP server {} has no command to run", name)));
+        }
+        
+        let mut command = Command::new(program.trim());
+        command
+            .args(args)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(windows)]
+        command.creation_flags(CREATE_NO_WINDOW);
+        
+        let mut child = command
+            .spawn()
+            .map_err(|e| AppError::config(format!("Failed to start MCP server {} ({}): {}", name, program, e)))?;
+        
+        let stdout = child.stdout.take().ok_or("MCP server stdout is not piped")?;
+        let stdin = Arc::new(AsyncMutex::new(child.stdin.take()));
+        let pending: Pending = Arc::default();
+        let exited = Arc::new(AtomicBool::new(false));
+        
+        tokio::spawn(read_messages(name.to_string(), stdout, stdin.clone(), pending.clone(), exited.clone()));
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(log_stderr(name.to_string(), stderr));
+        }
+        
+        Ok(Self {
+            name: name.to_string(),
+            child: AsyncMutex::new(child),
+            stdin,
+            pending,
+            exited,
+        })
+    }
+    
+    fn exited_error(&self) -> AppError {
+        AppError::new(ErrorKind::Network, format!("MCP server {} has stopped", self.name))
+    }
+}
+
+#[async_trait]
+impl Transport for StdioTransport {
+    async fn request(&self, id: u64, message: Value) -> Result<Value, AppError> {
+        let (sender, receiver) = oneshot::channel();
+        lock(&self.pending).insert(id, sender);
+        let _entry = PendingEntry { pending: &self.pending, id };
+        
+        // Checked after registering, so an exit in between still fails the request
+        if self.exited.load(Ordering::Relaxed) {
+            return Err(self.exited_error());
+        }
+        
+        write_message(&self.stdin, &message).await?;
+        
+        receiver.await.map_err(|_| self.exited_error())?
+    }
+    
+    async fn notify(&self, message: Value) -> Result<(), AppError> {
+        write_message(&self.stdin, &message).await
+    }
+    
+    fn is_alive(&self) -> bool {
+        !self.exited.load(Ordering::Relaxed)
+    }
+    
+    // Closing stdin asks the server to exit; it is killed if it doesn't
+    async fn close(&self) {
+        self.stdin.lock().await.take();
+        
+        let mut child = self.child.lock().await;
+        if tokio::time::timeout(EXIT_GRACE, child.wait()).await.is_err() {
+            if let Err(e) = child.kill().await {
+                eprintln!("Warning: Failed to stop MCP server {}: {}", self.name, e);
+            }
+        }
+    }
+}
+
+async fn write_message(stdin: &AsyncMutex<Option<ChildStdin>>, message: &Value) -> Result<(), AppError> {
+    let mut line = message.to_string();
+    line.push('\n');
+    
+    let mut stdin = stdin.lock().await;
+    let stdin = stdin
+        .as_mut()
+        .ok_or_else(|| AppError::new(ErrorKind::Network, "The MCP server has stopped"))?;
+    stdin
+        .write_all(line.as_bytes())
+        .await
+        .map_err(|e| AppError::new(ErrorKind::Network, format!("Failed to write to MCP server: {}", e)))?;
+    stdin
+        .flush()
+        .await
+        .map_err(|e| AppError::new(ErrorKind::Network, format!("Failed to write to MCP server: {}", e)))
+}
+
+// Dispatch the lines a stdio server writes: responses go to the request
+// waiting for them, requests from the server are answered (only ping is
+// supported), and notifications and lines that aren't JSON are ignored
+async fn read_messages(name: String, stdout: ChildStdout, stdin: Arc<AsyncMutex<Option<ChildStdin>>>, pending: Pending, exited: Arc<AtomicBool>) {
+    let mut lines = BufReader::new(stdout).lines();
+    
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("Warning: Failed to read from MCP server {}: {}", name, e);
+                break;
+            }
+        };
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        
+        match (message.get("id"), message.get("method").and_then(|m| m.as_str())) {
+            (Some(id), None) => {
+                let sender = id.as_u64().and_then(|id| lock(&pending).remove(&id));
+                if let Some(sender) = sender {
+                    let _ = sender.send(Ok(message));
+                }
+            }
+            (Some(id), Some(method)) => {
+                let response = if method == "ping" {
+                    json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+                } else {
+                    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32601, "message": "Method not found" } })
+                };
+                if let Err(e) = write_message(&stdin, &response).await {
+                    eprintln!("Warning: Failed to answer MCP server {}: {}", name, e);
+                }
+            }
+            _ => {}
+        }
+    }
+    
+    // The server exited: fail the requests still waiting
+    exited.store(true, Ordering::Relaxed);
+    for (_, sender) in lock(&pending).drain() {
+        let _ = sender.send(Err(AppError::new(ErrorKind::Network, format!("MCP server {} exited", name))));
+    }
+}
+
+async fn log_stderr(name: String, stderr: ChildStderr) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        eprintln!("[mcp {}] {}", name, line);
+    }
+}
+
+// A server reached over streamable HTTP: every message is POSTed, and the
+// answer comes back either as JSON or as a server-sent event stream
+pub struct HttpTransport {
+    name: String,
+    client: reqwest::Client,
+    url: String,
+    options: HttpOptions,
+    session_id: Mutex<Option<String>>, // Assigned by the server on initialize
+    protocol_version: Mutex<Option<String>>,
+}
+
+impl HttpTransport {
+    pub fn new(name: &str, client: reqwest::Client, url: &str, options: &HttpOptions) -> Result<Self, AppError> {
+        if url.trim().is_empty() {
+            return Err(AppError::config(format!("MCP server {} has no URL", name)));
+        }
+        
+        Ok(Self {
+            name: name.to_string(),
+            client,
+            url: url.trim().to_string(),
+            options: options.clone(),
+            session_id: Mutex::new(None),
+            protocol_version: Mutex::new(None),
+        })
+    }
+    
+    fn with_session(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let mut request = apply_http_options(request, &self.options);
+        if let Some(session_id) = lock(&self.session_id).as_deref() {
+            request = request.header("Mcp-Session-Id", session_id);
+        }
+        if let Some(version) = lock(&self.protocol_version).as_deref() {
+            request = request.header("MCP-Protocol-Version", version);
+        }
+        request
+    }
+    
+    async fn post(&self, message: &Value) -> Result<reqwest::Response, AppError> {
+        let response = self
+            .with_session(self.client.post(&self.url))
+            .header("Accept", "application/json, text/event-stream")
+            .json(message)
+            .send()
+            .await
+            .map_err(|e| request_error(&e, format!("Failed to connect to MCP server {} at {}: {}", self.name, self.url, e)))?;
+        
+        if !response.status().is_success() {
+            return Err(error_response(response, &format!("MCP server {}", self.name)).await);
+        }
+        
+        if let Some(session_id) = response.headers().get("mcp-session-id").and_then(|v| v.to_str().ok()) {
+            *lock(&self.session_id) = Some(session_id.to_string());
+        }
+        
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl Transport for HttpTransport {
+    async fn request(&self, id: u64, message: Value) -> Result<Value, AppError> {
+        let response = self.post(&message).await?;
+        let is_event_stream = response
+            .headers()
+            .get("content-type")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.starts_with("text/event-stream"))
+            .unwrap_or(false);
+        
+        if !is_event_stream {
+            return response
+                .json()
+                .await
+                .map_err(|e| AppError::invalid_response(format!("Invalid response from MCP server {}: {}", self.name, e)));
+        }
+        
+        // The stream may carry notifications before the response
+        let is_response = |data: &str| {
+            serde_json::from_str::<Value>(data)
+                .ok()
+                .filter(|m| m.get("method").is_none() && m.get("id").and_then(|i| i.as_u64()) == Some(id))
+        };
+        
+        let mut stream = response.bytes_stream();
+        let mut decoder = SseDecoder::default();
+        let mut events = Vec::new();
+        
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| request_error(&e, format!("MCP server {} stream error: {}", self.name, e)))?;
+            decoder.push(&chunk, &mut events);
+            if let Some(message) = events.drain(..).find_map(|event| is_response(&event.data)) {
+                return Ok(message);
+            }
+        }
+        
+        decoder.finish(&mut events);
+        let message = events.iter().find_map(|event| is_response(&event.data));
+        message.ok_or_else(|| AppError::invalid_response(format!("MCP server {} closed the stream without answering", self.name)))
+    }
+    
+    async fn notify(&self, message: Value) -> Result<(), AppError> {
+        self.post(&message).await.map(|_| ())
+    }
+    
+    fn set_protocol_version(&self, version: &str) {
+        *lock(&self.protocol_version) = Some(version.to_string());
+    }
+    
+    // Ends the session on the server; servers that don't keep sessions may refuse
+    async fn close(&self) {
+        if lock(&self.session_id).is_none() {
+            return;
+        }
+        
+        if let Err(e) = self.with_session(self.client.delete(&self.url)).send().await {
+            eprintln!("Warning: Failed to close MCP session with {}: {}", self.name, e);
+        }
+    }
+}
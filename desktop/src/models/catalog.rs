@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+// A model offered by a provider, as shown in the model picker
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub id: String,
+    #[serde(rename = "displayName")]
+    pub display_name: String,
+    #[serde(rename = "contextLength", skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u64>,
+    // Whether the model reads images; None when the provider doesn't say
+    #[serde(default)]
+    pub vision: Option<bool>,
+    pub tools: bool, // False when the provider doesn't report it
+}
+
+impl ModelInfo {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            display_name: id.to_string(),
+            context_length: None,
+            vision: None,
+            tools: false,
+        }
+    }
+}
@@ -1,18 +1,126 @@
 use serde::{Deserialize, Serialize};
+use crate::models::{ContextPolicy, GenerationParams};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
-    pub role: String, // "system", "user", or "assistant"
+    pub role: String, // "system", "user", "assistant", or "tool" for the result of a tool call
     pub content: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reasoning: String, // Thinking a reasoning model streamed before its answer
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub reasoning_signature: String, // Anthropic's signature, needed to send the thinking back
     pub timestamp: String, // ISO 8601 timestamp
     #[serde(default = "default_complete")]
     pub complete: bool, // Whether this message is complete (false for streaming/incomplete messages)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>, // Sources the provider grounded the answer on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub provider: String, // Provider setting that produced an assistant message
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub model: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool, // Always sent to the LLM, whatever the context policy
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageAttachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<FileAttachment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>, // Tools an assistant message asked to run
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tool_call_id: String, // For tool messages: the call this is the result of
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tool_name: String,
 }
 
 fn default_complete() -> bool {
     true
 }
 
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Self {
+            role: String::new(),
+            content: String::new(),
+            reasoning: String::new(),
+            reasoning_signature: String::new(),
+            timestamp: String::new(),
+            complete: default_complete(),
+            citations: Vec::new(),
+            usage: None,
+            provider: String::new(),
+            model: String::new(),
+            pinned: false,
+            images: Vec::new(),
+            files: Vec::new(),
+            tool_calls: Vec::new(),
+            tool_call_id: String::new(),
+            tool_name: String::new(),
+        }
+    }
+}
+
+// An image attached to a message. The file is stored once in the app data
+// `attachments` directory and referenced by name, so conversations don't
+// embed the image data; it is only loaded when the message is sent.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ImageAttachment {
+    pub file: String, // Name in the attachments directory: content hash and extension
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String, // Original file name
+    #[serde(skip)]
+    pub data: String, // Base64 data, filled in for provider requests only
+}
+
+// A document attached to a message. Its extracted text is kept on the message,
+// within the attachment size limit, and sent with the message on every turn
+// the context policy keeps it.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FileAttachment {
+    pub name: String, // Original file name
+    pub format: String, // "text", "markdown", "pdf", "docx", or the language of source code
+    pub content: String, // Extracted text
+    #[serde(default)]
+    pub tokens: u64, // Estimated token count of the content
+}
+
+// A tool the model asked to run. Providers that don't assign ids get
+// generated ones so results can be matched to calls.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub arguments: serde_json::Value,
+}
+
+// Token counts reported by the provider for one response, with the estimated
+// cost in USD when the model's price is known
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u64, // Including cached tokens
+    #[serde(default)]
+    pub completion_tokens: u64,
+    #[serde(default)]
+    pub cached_tokens: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Citation {
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Conversation {
     pub id: String,
@@ -20,6 +128,34 @@ pub struct Conversation {
     pub created_at: String, // ISO 8601 timestamp
     pub updated_at: String, // ISO 8601 timestamp
     pub messages: Vec<Message>,
+    #[serde(default, skip_serializing_if = "GenerationParams::is_empty")]
+    pub generation: GenerationParams, // Overrides the provider's generation defaults
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub persona: Option<String>, // Name of the persona the conversation was started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_policy: Option<ContextPolicy>, // Overrides the "context-policy" setting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ContextSummary>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_usage: Vec<UsageRecord>, // Requests made for the conversation besides its answers
+}
+
+// Token usage of a request that produced no message, such as writing the
+// context summary, kept so the usage summary counts it like an answer
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct UsageRecord {
+    pub timestamp: String, // ISO 8601 timestamp
+    pub provider: String,
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+// Rolling summary of the oldest messages of a conversation, kept up to date
+// by the summarize context policy
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ContextSummary {
+    pub content: String,
+    pub covered: usize, // Number of leading messages the summary replaces
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -0,0 +1,125 @@
+use std::fmt;
+use serde::Serialize;
+
+// What went wrong, so the frontend can offer a fix (open settings, pull the
+// model, retry) rather than only showing the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    Config, // Missing or invalid settings: URL, model, endpoint, network options
+    Auth, // API key missing or rejected
+    Network, // Server unreachable
+    Timeout,
+    RateLimit,
+    ModelNotFound,
+    InvalidRequest, // The provider rejected the request (bad parameters, context too long, blocked prompt)
+    Server, // The provider failed or is overloaded
+    Storage, // Reading or writing app data failed
+    Corrupt, // A conversation or settings file can't be parsed
+    NotFound, // Conversation, message or stream doesn't exist
+    Unsupported, // The input can't be handled (images to a text-only model, an unknown file type)
+    Cancelled,
+    Internal,
+}
+
+impl ErrorKind {
+    // Whether sending the same request again may succeed
+    pub fn is_retryable(self) -> bool {
+        matches!(self, ErrorKind::Network | ErrorKind::Timeout | ErrorKind::RateLimit | ErrorKind::Server)
+    }
+}
+
+// Error returned by commands, serialized as {kind, message, provider, retryable, hint}
+#[derive(Debug, Clone, Serialize)]
+pub struct AppError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub provider: Option<String>, // Provider setting the error came from
+    pub retryable: bool,
+    pub hint: Option<String>, // Suggested fix shown under the message
+}
+
+impl AppError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            provider: None,
+            retryable: kind.is_retryable(),
+            hint: None,
+        }
+    }
+    
+    pub fn config(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Config, message)
+    }
+    
+    pub fn auth(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Auth, message)
+    }
+    
+    pub fn storage(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Storage, message)
+    }
+    
+    pub fn corrupt(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Corrupt, message)
+    }
+    
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+    
+    // A response a provider sent that couldn't be understood
+    pub fn invalid_response(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Server, message)
+    }
+    
+    // Classify an HTTP error status returned by a provider. 404 usually means
+    // an unknown model, unless the body doesn't mention one, in which case the
+    // endpoint URL is likely wrong.
+    pub fn from_status(status: u16, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let kind = match status {
+            401 | 403 => ErrorKind::Auth,
+            404 if message.to_lowercase().contains("model") => ErrorKind::ModelNotFound,
+            404 | 405 => ErrorKind::Config,
+            408 => ErrorKind::Timeout,
+            429 => ErrorKind::RateLimit,
+            500..=599 => ErrorKind::Server,
+            _ => ErrorKind::InvalidRequest,
+        };
+        Self::new(kind, message)
+    }
+    
+    pub fn with_provider(mut self, provider: &str) -> Self {
+        self.provider = Some(provider.to_string());
+        self
+    }
+    
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// Errors from helpers that still report plain strings
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
@@ -1,6 +1,11 @@
 pub mod settings;
 pub mod conversation;
+pub mod catalog;
+pub mod usage;
+pub mod error;
 
 pub use settings::*;
 pub use conversation::*;
-
+pub use catalog::*;
+pub use usage::*;
+pub use error::*;
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, HashMap};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -7,17 +8,144 @@ pub struct ProviderParams {
     #[serde(default)]
     pub openai: OpenAIParams,
     #[serde(default)]
+    pub anthropic: AnthropicParams,
+    #[serde(default)]
     pub google: GoogleParams,
     #[serde(default)]
     pub lmstudio: LMStudioParams,
     #[serde(default)]
     pub custom: CustomParams,
+    #[serde(default, rename = "openai-compatible")]
+    pub openai_compatible: BTreeMap<String, OpenAICompatibleParams>, // Named endpoints, selected as "openai-compatible:<name>"
+}
+
+// Extra HTTP headers and authentication sent with every request to a
+// provider, e.g. for a reverse proxy in front of Ollama or OpenAI's
+// OpenAI-Organization/OpenAI-Project headers. Values of `secret-headers` and
+// the auth secrets (fields ending in `_key`) live in encrypted storage.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct HttpOptions {
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default, rename = "secret-headers")]
+    pub secret_headers: HashMap<String, String>,
+    #[serde(default)]
+    pub auth: AuthMode,
+}
+
+// Authentication added to a provider's requests on top of its own API key
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum AuthMode {
+    #[default]
+    None,
+    Bearer {
+        #[serde(default)]
+        token_key: String,
+    },
+    Basic {
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        password_key: String,
+    },
+    Header {
+        #[serde(default)]
+        name: String, // e.g. "X-Api-Key"
+        #[serde(default)]
+        value_key: String,
+    },
+}
+
+// Sampling and length settings sent with each request. Unset fields are left
+// to the provider's own defaults. Every provider has a set of defaults in its
+// params; a conversation can override individual fields.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct GenerationParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(default, rename = "top-p", skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+    #[serde(default, rename = "max-tokens", skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, rename = "ollama-options", skip_serializing_if = "serde_json::Map::is_empty")]
+    pub ollama_options: serde_json::Map<String, serde_json::Value>, // Extra Ollama `options` such as num_ctx or repeat_penalty
+    #[serde(default, rename = "thinking-effort", skip_serializing_if = "Option::is_none")]
+    pub thinking_effort: Option<ThinkingEffort>, // How much reasoning models think before answering
+    #[serde(default, rename = "thinking-budget", skip_serializing_if = "Option::is_none")]
+    pub thinking_budget: Option<u32>, // Thinking tokens for Anthropic and Gemini, instead of the effort's
+}
+
+// Reasoning effort, sent as the provider's effort level (OpenAI, Ollama,
+// OpenAI-compatible servers) or turned into a token budget (Anthropic, Gemini)
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThinkingEffort {
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+impl ThinkingEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThinkingEffort::Off => "off",
+            ThinkingEffort::Low => "low",
+            ThinkingEffort::Medium => "medium",
+            ThinkingEffort::High => "high",
+        }
+    }
+}
+
+impl GenerationParams {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    
+    // Apply `overrides` on top of these defaults; fields set in `overrides` win
+    pub fn merge(&self, overrides: &GenerationParams) -> GenerationParams {
+        let mut ollama_options = self.ollama_options.clone();
+        ollama_options.extend(overrides.ollama_options.clone());
+        
+        GenerationParams {
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            stop: if overrides.stop.is_empty() { self.stop.clone() } else { overrides.stop.clone() },
+            seed: overrides.seed.or(self.seed),
+            ollama_options,
+            thinking_effort: overrides.thinking_effort.or(self.thinking_effort),
+            thinking_budget: overrides.thinking_budget.or(self.thinking_budget),
+        }
+    }
+    
+    // Thinking token budget: none when thinking isn't configured, zero when
+    // it is turned off, otherwise `thinking-budget` or the effort's default
+    pub fn thinking_tokens(&self) -> Option<u32> {
+        match (self.thinking_effort, self.thinking_budget) {
+            (Some(ThinkingEffort::Off), _) => Some(0),
+            (_, Some(budget)) => Some(budget),
+            (Some(ThinkingEffort::Low), None) => Some(2_048),
+            (Some(ThinkingEffort::Medium), None) => Some(8_192),
+            (Some(ThinkingEffort::High), None) => Some(24_576),
+            (None, None) => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OllamaParams {
     pub url: String,
     pub model: String,
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
 }
 
 impl Default for OllamaParams {
@@ -25,6 +153,8 @@ impl Default for OllamaParams {
         Self {
             url: "http://localhost:11434".to_string(),
             model: "gemma2:2b".to_string(),
+            generation: GenerationParams::default(),
+            http: HttpOptions::default(),
         }
     }
 }
@@ -32,15 +162,83 @@ impl Default for OllamaParams {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OpenAIParams {
     pub api_key: String,
+    #[serde(default = "default_openai_model")]
+    pub model: String,
     #[serde(default, rename = "web-search-enabled")]
-    pub web_search_enabled: bool,
+    pub web_search_enabled: bool, // Only honored by models that support web search
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+pub fn default_openai_model() -> String {
+    "gpt-4o-mini".to_string()
+}
+
+impl OpenAIParams {
+    // The search preview models (e.g. gpt-4o-search-preview) only work with
+    // Chat Completions; configs that chose one get its base model with the
+    // Responses API's web search instead
+    pub fn migrate_search_model(&mut self) {
+        if let Some((base, _)) = self.model.split_once("-search-") {
+            self.model = base.to_string();
+            self.web_search_enabled = true;
+        }
+    }
 }
 
 impl Default for OpenAIParams {
     fn default() -> Self {
         Self {
             api_key: "__NOT_STORED_HERE__".to_string(),
+            model: default_openai_model(),
             web_search_enabled: false,
+            generation: GenerationParams::default(),
+            http: HttpOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AnthropicParams {
+    pub api_key: String,
+    #[serde(default = "default_anthropic_model")]
+    pub model: String,
+    #[serde(default = "default_anthropic_max_tokens", rename = "max-tokens")]
+    pub max_tokens: u32, // Required by the Messages API; `generation.max_tokens` takes precedence
+    #[serde(default = "default_true", rename = "cache-system-prompt")]
+    pub cache_system_prompt: bool, // Mark the system prompt for prompt caching
+    #[serde(default = "default_true", rename = "cache-history")]
+    pub cache_history: bool, // Mark the conversation prefix for prompt caching
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+pub fn default_anthropic_model() -> String {
+    "claude-sonnet-4-5".to_string()
+}
+
+pub fn default_anthropic_max_tokens() -> u32 {
+    4096
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for AnthropicParams {
+    fn default() -> Self {
+        Self {
+            api_key: "__NOT_STORED_HERE__".to_string(),
+            model: default_anthropic_model(),
+            max_tokens: default_anthropic_max_tokens(),
+            cache_system_prompt: true,
+            cache_history: true,
+            generation: GenerationParams::default(),
+            http: HttpOptions::default(),
         }
     }
 }
@@ -48,20 +246,55 @@ impl Default for OpenAIParams {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GoogleParams {
     pub api_key: String,
+    #[serde(default = "default_google_model")]
+    pub model: String,
+    #[serde(default, rename = "web-search-enabled")]
+    pub web_search_enabled: bool, // Ground answers with Google Search
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+pub fn default_google_model() -> String {
+    "gemini-2.5-flash".to_string()
 }
 
 impl Default for GoogleParams {
     fn default() -> Self {
         Self {
             api_key: "__NOT_STORED_HERE__".to_string(),
+            model: default_google_model(),
+            web_search_enabled: false,
+            generation: GenerationParams::default(),
+            http: HttpOptions::default(),
         }
     }
 }
 
+// Any server exposing the OpenAI chat completions API (vLLM, llama.cpp server,
+// OpenRouter, Groq, Together, corporate gateways, ...)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OpenAICompatibleParams {
+    #[serde(rename = "base-url")]
+    pub base_url: String, // Including the version prefix, e.g. "https://api.groq.com/openai/v1"
+    pub model: String,
+    #[serde(default = "default_api_key_marker")]
+    pub api_key: String, // Optional for local servers
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LMStudioParams {
     pub url: String,
     pub model: String,
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
 }
 
 impl Default for LMStudioParams {
@@ -69,19 +302,96 @@ impl Default for LMStudioParams {
         Self {
             url: "http://localhost:1234".to_string(),
             model: "local-model".to_string(),
+            generation: GenerationParams::default(),
+            http: HttpOptions::default(),
         }
     }
 }
 
+// Describes an arbitrary HTTP LLM endpoint. The body template is JSON in which
+// "{{messages}}", "{{model}}", "{{prompt}}" and "{{stream}}" are substituted,
+// as are the generation parameters "{{temperature}}", "{{top_p}}",
+// "{{max_tokens}}", "{{stop}}" and "{{seed}}" (null when unset);
+// "{{api_key}}" and "{{model}}" may also be used in header values.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CustomParams {
     pub url: String,
+    #[serde(default = "default_custom_method")]
+    pub method: String,
+    #[serde(default = "default_api_key_marker")]
+    pub api_key: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default = "default_custom_body_template", rename = "body-template")]
+    pub body_template: String,
+    #[serde(default = "default_custom_response_path", rename = "response-path")]
+    pub response_path: String, // JSON pointer ("/choices/0/message/content") or dot path ("choices.0.message.content")
+    #[serde(default, rename = "stream-format")]
+    pub stream_format: CustomStreamFormat,
+    #[serde(default = "default_custom_delta_path", rename = "delta-path")]
+    pub delta_path: String, // Path to the text delta within each streamed event
+    #[serde(default = "default_custom_done_marker", rename = "done-marker")]
+    pub done_marker: String, // SSE data payload that ends the stream
+    #[serde(default = "default_custom_usage_path", rename = "usage-path")]
+    pub usage_path: String, // Path to an OpenAI-style `usage` object (prompt_tokens, completion_tokens)
+    #[serde(default)]
+    pub generation: GenerationParams,
+    #[serde(flatten)]
+    pub http: HttpOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomStreamFormat {
+    #[default]
+    None,
+    Ndjson,
+    Sse,
+}
+
+pub fn default_custom_method() -> String {
+    "POST".to_string()
+}
+
+fn default_api_key_marker() -> String {
+    "__NOT_STORED_HERE__".to_string()
+}
+
+pub fn default_custom_body_template() -> String {
+    r#"{"model": "{{model}}", "messages": "{{messages}}", "stream": "{{stream}}"}"#.to_string()
+}
+
+pub fn default_custom_response_path() -> String {
+    "/choices/0/message/content".to_string()
+}
+
+pub fn default_custom_delta_path() -> String {
+    "/choices/0/delta/content".to_string()
+}
+
+pub fn default_custom_done_marker() -> String {
+    "[DONE]".to_string()
+}
+
+pub fn default_custom_usage_path() -> String {
+    "/usage".to_string()
 }
 
 impl Default for CustomParams {
     fn default() -> Self {
         Self {
             url: String::new(),
+            method: default_custom_method(),
+            api_key: default_api_key_marker(),
+            model: String::new(),
+            body_template: default_custom_body_template(),
+            response_path: default_custom_response_path(),
+            stream_format: CustomStreamFormat::default(),
+            delta_path: default_custom_delta_path(),
+            done_marker: default_custom_done_marker(),
+            usage_path: default_custom_usage_path(),
+            generation: GenerationParams::default(),
+            http: HttpOptions::default(),
         }
     }
 }
@@ -91,16 +401,148 @@ impl Default for ProviderParams {
         Self {
             ollama: OllamaParams::default(),
             openai: OpenAIParams::default(),
+            anthropic: AnthropicParams::default(),
             google: GoogleParams::default(),
             lmstudio: LMStudioParams::default(),
             custom: CustomParams::default(),
+            openai_compatible: BTreeMap::new(),
         }
     }
 }
 
+// A reusable system prompt, optionally with its own provider, model and
+// generation parameters. Conversations started with a persona use these
+// instead of the selected provider's settings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Persona {
+    pub name: String,
+    #[serde(default, rename = "system-prompt")]
+    pub system_prompt: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<String>, // Provider setting, e.g. "ollama" or "openai-compatible:groq"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "GenerationParams::is_empty")]
+    pub generation: GenerationParams,
+}
+
+// Settings of the HTTP client shared by all provider requests. Unset
+// timeouts use the defaults in `api/http.rs`.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct NetworkSettings {
+    #[serde(default, rename = "connect-timeout-secs", skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_secs: Option<u64>,
+    #[serde(default, rename = "read-timeout-secs", skip_serializing_if = "Option::is_none")]
+    pub read_timeout_secs: Option<u64>, // Longest wait for data; a stream may take longer overall
+    #[serde(default, rename = "idle-timeout-secs", skip_serializing_if = "Option::is_none")]
+    pub idle_timeout_secs: Option<u64>, // How long unused connections are kept open
+    #[serde(default)]
+    pub proxy: String, // e.g. "http://proxy.corp:3128"; empty uses HTTP(S)_PROXY from the environment
+    #[serde(default, rename = "no-proxy")]
+    pub no_proxy: String, // Comma-separated hosts, domains and IP ranges reached directly
+    #[serde(default, rename = "ca-certificates")]
+    pub ca_certificates: Vec<String>, // Paths of PEM files with extra root certificates
+    #[serde(default, rename = "client-certificate")]
+    pub client_certificate: String, // Path of a PEM certificate chain for mutual TLS
+    #[serde(default, rename = "client-key")]
+    pub client_key: String, // Path of the PKCS#8 PEM key for the client certificate
+}
+
+// Search API behind the built-in `web_search` tool: a SearXNG instance, or
+// any JSON API taking `q` and returning a `results` array in the same shape
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct WebSearchSettings {
+    #[serde(default)]
+    pub url: String, // Empty leaves the tool out
+    #[serde(default = "default_search_results", rename = "max-results")]
+    pub max_results: usize,
+    #[serde(default, rename = "fetch-pages")]
+    pub fetch_pages: bool, // Also send the readable text of each result page
+}
+
+pub fn default_search_results() -> usize {
+    5
+}
+
+impl Default for WebSearchSettings {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_results: default_search_results(),
+            fetch_pages: false,
+        }
+    }
+}
+
+// How an MCP server is reached
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum McpTransport {
+    #[default]
+    Stdio, // A child process speaking JSON-RPC on stdin/stdout
+    Http, // Streamable HTTP
+}
+
+// Whether a tool of an MCP server may run without asking the user
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolApproval {
+    #[default]
+    Ask,
+    Allow,
+    Deny, // Not offered to the model
+}
+
+// A Model Context Protocol server whose tools are offered to the model, kept
+// in settings by name. Values of `secret-env`, `secret-headers` and the auth
+// secrets live in encrypted storage.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct McpServer {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub transport: McpTransport,
+    #[serde(default)]
+    pub command: String, // stdio: program to launch
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    #[serde(default, rename = "secret-env")]
+    pub secret_env: HashMap<String, String>,
+    #[serde(default)]
+    pub url: String, // http: endpoint of the server
+    #[serde(flatten)]
+    pub http: HttpOptions,
+    #[serde(default, rename = "tool-approval")]
+    pub tool_approval: HashMap<String, ToolApproval>, // Tools not listed ask every time
+}
+
+// How much of a conversation's history is sent with each request. A turn is a
+// user message and the replies to it. Pinned messages are always sent.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(tag = "mode", rename_all = "kebab-case")]
+pub enum ContextPolicy {
+    #[default]
+    All,
+    LastTurns {
+        turns: usize,
+    },
+    TokenBudget {
+        tokens: u64, // Estimated, at roughly four characters per token
+    },
+    // Older turns are replaced by a summary written by the conversation's provider
+    Summarize {
+        #[serde(rename = "keep-turns")]
+        keep_turns: usize,
+    },
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Settings {
     pub provider: String,
+    #[serde(rename = "fallback-providers", default)]
+    pub fallback_providers: Vec<String>, // Tried in order when the provider can't be reached or lacks the model
     #[serde(rename = "provider-params")]
     pub provider_params: ProviderParams,
     #[serde(rename = "conversation-history")]
@@ -115,6 +557,20 @@ pub struct Settings {
     pub window_x: Option<i32>,
     #[serde(rename = "window-y", skip_serializing_if = "Option::is_none")]
     pub window_y: Option<i32>,
+    #[serde(default)]
+    pub personas: Vec<Persona>,
+    #[serde(rename = "context-policy", default)]
+    pub context_policy: ContextPolicy,
+    #[serde(default)]
+    pub network: NetworkSettings,
+    #[serde(rename = "tools-enabled", default)]
+    pub tools_enabled: bool, // Offer the tool registry to models that support tool calls
+    #[serde(rename = "mcp-servers", default)]
+    pub mcp_servers: BTreeMap<String, McpServer>,
+    #[serde(rename = "web-search", default)]
+    pub web_search: WebSearchSettings,
+    #[serde(rename = "reasoning-in-history", default)]
+    pub reasoning_in_history: bool, // Send the reasoning of earlier answers back to providers that accept it
 }
 
 pub fn default_setup_completed() -> bool {
@@ -133,6 +589,7 @@ impl Default for Settings {
     fn default() -> Self {
         Self {
             provider: "ollama".to_string(),
+            fallback_providers: Vec::new(),
             provider_params: ProviderParams::default(),
             conversation_history: true,
             keyboard_shortcut: default_shortcut(),
@@ -140,6 +597,13 @@ impl Default for Settings {
             theme: default_theme(),
             window_x: None,
             window_y: None,
+            personas: Vec::new(),
+            context_policy: ContextPolicy::default(),
+            network: NetworkSettings::default(),
+            tools_enabled: false,
+            mcp_servers: BTreeMap::new(),
+            web_search: WebSearchSettings::default(),
+            reasoning_in_history: false,
         }
     }
 }
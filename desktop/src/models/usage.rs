@@ -0,0 +1,51 @@
+use serde::Serialize;
+use crate::models::TokenUsage;
+
+// Token and cost totals over a set of assistant responses
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct UsageTotals {
+    pub responses: u64,
+    #[serde(rename = "promptTokens")]
+    pub prompt_tokens: u64,
+    #[serde(rename = "completionTokens")]
+    pub completion_tokens: u64,
+    #[serde(rename = "cachedTokens")]
+    pub cached_tokens: u64,
+    pub cost: f64, // Sum of the known estimates
+    #[serde(rename = "unpricedResponses")]
+    pub unpriced_responses: u64, // Responses from models missing from the price table
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &TokenUsage) {
+        self.responses += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.cached_tokens += usage.cached_tokens;
+        match usage.cost {
+            Some(cost) => self.cost += cost,
+            None => self.unpriced_responses += 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct UsageGroup {
+    pub key: String, // Conversation id, day (YYYY-MM-DD), provider setting or model
+    pub label: String,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct UsageSummary {
+    pub total: UsageTotals,
+    #[serde(rename = "byConversation")]
+    pub by_conversation: Vec<UsageGroup>,
+    #[serde(rename = "byDay")]
+    pub by_day: Vec<UsageGroup>,
+    #[serde(rename = "byProvider")]
+    pub by_provider: Vec<UsageGroup>,
+    #[serde(rename = "byModel")]
+    pub by_model: Vec<UsageGroup>,
+}
@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager};
+use sha2::{Sha256, Digest};
+use base64::{Engine as _, engine::general_purpose};
+
+use crate::models::{AppError, ErrorKind, ImageAttachment, Message};
+use crate::storage::conversations::list_conversations;
+
+const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024; // Providers reject larger images
+const UNUSED_GRACE: Duration = Duration::from_secs(60 * 60); // Attached but not sent yet
+
+// Image types every vision provider accepts, with their file extensions
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+];
+
+pub fn get_attachments_dir(app: &AppHandle) -> PathBuf {
+    app.path()
+        .app_data_dir()
+        .expect("Failed to get app data directory")
+        .join("attachments")
+}
+
+// Attachment file names are generated here; anything else could point outside
+// the attachments directory
+fn validate_file_name(file: &str) -> Result<(), AppError> {
+    let valid = !file.is_empty()
+        && !file.starts_with('.')
+        && file.chars().all(|c| c.is_ascii_alphanumeric() || c == '.');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid attachment name: {}", file).into())
+    }
+}
+
+// Store an image and return the reference kept on the message. Images are
+// named by content hash, so attaching the same image twice stores it once.
+pub fn save_image(app: &AppHandle, data: &[u8], mime_type: &str, name: &str) -> Result<ImageAttachment, AppError> {
+    let mime_type = mime_type.trim().to_lowercase();
+    let extension = IMAGE_TYPES
+        .iter()
+        .find(|(t, _)| *t == mime_type)
+        .map(|(_, extension)| *extension)
+        .ok_or_else(|| AppError::new(
+            ErrorKind::Unsupported,
+            format!("Unsupported image type {}: use PNG, JPEG, GIF or WebP", mime_type),
+        ))?;
+    
+    if data.len() > MAX_IMAGE_BYTES {
+        return Err(AppError::new(
+            ErrorKind::Unsupported,
+            format!("Image is too large ({} MB, at most {} MB)", data.len() / (1024 * 1024), MAX_IMAGE_BYTES / (1024 * 1024)),
+        ));
+    }
+    
+    let attachments_dir = get_attachments_dir(app);
+    fs::create_dir_all(&attachments_dir)
+        .map_err(|e| AppError::storage(format!("Failed to create attachments directory: {}", e)))?;
+    
+    let hash = Sha256::digest(data);
+    let file = format!("{}.{}", hash.iter().map(|b| format!("{:02x}", b)).collect::<String>(), extension);
+    let file_path = attachments_dir.join(&file);
+    
+    if !file_path.exists() {
+        fs::write(&file_path, data)
+            .map_err(|e| AppError::storage(format!("Failed to write attachment: {}", e)))?;
+    }
+    
+    Ok(ImageAttachment {
+        file,
+        mime_type,
+        name: name.to_string(),
+        data: String::new(),
+    })
+}
+
+// Base64 data of a stored image
+pub fn load_image_data(app: &AppHandle, file: &str) -> Result<String, AppError> {
+    validate_file_name(file)?;
+    
+    let file_path = get_attachments_dir(app).join(file);
+    if !file_path.exists() {
+        return Err(AppError::not_found(format!("Attachment {} not found", file)));
+    }
+    
+    let data = fs::read(&file_path)
+        .map_err(|e| AppError::storage(format!("Failed to read attachment {}: {}", file, e)))?;
+    
+    Ok(general_purpose::STANDARD.encode(data))
+}
+
+// Fill in the data of every image of messages about to be sent to a provider
+pub fn load_message_images(app: &AppHandle, messages: &mut [Message]) -> Result<(), AppError> {
+    for image in messages.iter_mut().flat_map(|m| m.images.iter_mut()) {
+        image.data = load_image_data(app, &image.file)?;
+    }
+    Ok(())
+}
+
+// Delete stored images no conversation refers to anymore. Recent files are
+// kept, as they may be attached to a message that hasn't been sent yet.
+pub fn remove_unused_images(app: &AppHandle) -> Result<(), AppError> {
+    let attachments_dir = get_attachments_dir(app);
+    if !attachments_dir.exists() {
+        return Ok(());
+    }
+    
+    let used: HashSet<String> = list_conversations(app)?
+        .into_iter()
+        .flat_map(|c| c.messages)
+        .flat_map(|m| m.images)
+        .map(|image| image.file)
+        .collect();
+    
+    let entries = fs::read_dir(&attachments_dir)
+        .map_err(|e| AppError::storage(format!("Failed to read attachments directory: {}", e)))?;
+    
+    for entry in entries.flatten() {
+        let file = entry.file_name().to_string_lossy().to_string();
+        let recent = entry.metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map(|age| age < UNUSED_GRACE)
+            .unwrap_or(true);
+        if !used.contains(&file) && !recent {
+            fs::remove_file(entry.path())
+                .map_err(|e| AppError::storage(format!("Failed to delete attachment {}: {}", file, e)))?;
+        }
+    }
+    
+    Ok(())
+}
@@ -4,7 +4,7 @@ use tauri::{AppHandle, Manager};
 use chrono::{DateTime, Utc};
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use crate::models::{Conversation, ConversationMetadata, Message};
+use crate::models::{AppError, Conversation, ConversationMetadata, FileAttachment, GenerationParams, ImageAttachment, Message};
 
 pub fn get_conversations_dir(app: &AppHandle) -> PathBuf {
     app.path()
@@ -14,26 +14,26 @@ pub fn get_conversations_dir(app: &AppHandle) -> PathBuf {
 }
 
 // Validate conversation ID to prevent path traversal attacks
-pub fn validate_conversation_id(id: &str) -> Result<(), String> {
+pub fn validate_conversation_id(id: &str) -> Result<(), AppError> {
     // Only allow alphanumeric characters, hyphens, and underscores
     // This prevents path traversal (../) and other malicious patterns
     if id.is_empty() {
-        return Err("Conversation ID cannot be empty".to_string());
+        return Err("Conversation ID cannot be empty".into());
     }
     
     if id.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
         Ok(())
     } else {
-        Err(format!("Invalid conversation ID: contains invalid characters"))
+        Err("Invalid conversation ID: contains invalid characters".into())
     }
 }
 
-pub fn save_conversation(app: &AppHandle, conversation: &Conversation) -> Result<(), String> {
+pub fn save_conversation(app: &AppHandle, conversation: &Conversation) -> Result<(), AppError> {
     let conversations_dir = get_conversations_dir(app);
     
     // Ensure the conversations directory exists
     fs::create_dir_all(&conversations_dir)
-        .map_err(|e| format!("Failed to create conversations directory: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to create conversations directory: {}", e)))?;
     
     // Create file path: conversations/{id}.json
     let file_path = conversations_dir.join(format!("{}.json", conversation.id));
@@ -44,12 +44,12 @@ pub fn save_conversation(app: &AppHandle, conversation: &Conversation) -> Result
     
     // Write to file
     fs::write(&file_path, json)
-        .map_err(|e| format!("Failed to write conversation file: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to write conversation file: {}", e)))?;
     
     Ok(())
 }
 
-pub fn load_conversation(app: &AppHandle, conversation_id: &str) -> Result<Conversation, String> {
+pub fn load_conversation(app: &AppHandle, conversation_id: &str) -> Result<Conversation, AppError> {
     // Validate conversation ID to prevent path traversal
     validate_conversation_id(conversation_id)?;
     
@@ -57,19 +57,19 @@ pub fn load_conversation(app: &AppHandle, conversation_id: &str) -> Result<Conve
     let file_path = conversations_dir.join(format!("{}.json", conversation_id));
     
     if !file_path.exists() {
-        return Err(format!("Conversation {} not found", conversation_id));
+        return Err(AppError::not_found(format!("Conversation {} not found", conversation_id)));
     }
     
     let content = fs::read_to_string(&file_path)
-        .map_err(|e| format!("Failed to read conversation file: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to read conversation file: {}", e)))?;
     
     let conversation: Conversation = serde_json::from_str(&content)
-        .map_err(|e| format!("Failed to parse conversation JSON: {}", e))?;
+        .map_err(|e| AppError::corrupt(format!("Failed to parse conversation JSON: {}", e)))?;
     
     Ok(conversation)
 }
 
-pub fn list_conversations(app: &AppHandle) -> Result<Vec<Conversation>, String> {
+pub fn list_conversations(app: &AppHandle) -> Result<Vec<Conversation>, AppError> {
     let conversations_dir = get_conversations_dir(app);
     
     // Ensure directory exists
@@ -81,10 +81,10 @@ pub fn list_conversations(app: &AppHandle) -> Result<Vec<Conversation>, String>
     
     // Read all JSON files in the conversations directory
     let entries = fs::read_dir(&conversations_dir)
-        .map_err(|e| format!("Failed to read conversations directory: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to read conversations directory: {}", e)))?;
     
     for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let entry = entry.map_err(|e| AppError::storage(format!("Failed to read directory entry: {}", e)))?;
         let path = entry.path();
         
         // Only process .json files
@@ -111,7 +111,7 @@ pub fn list_conversations(app: &AppHandle) -> Result<Vec<Conversation>, String>
     Ok(conversations)
 }
 
-pub fn delete_conversation(app: &AppHandle, conversation_id: &str) -> Result<(), String> {
+pub fn delete_conversation(app: &AppHandle, conversation_id: &str) -> Result<(), AppError> {
     // Validate conversation ID to prevent path traversal
     validate_conversation_id(conversation_id)?;
     
@@ -119,16 +119,16 @@ pub fn delete_conversation(app: &AppHandle, conversation_id: &str) -> Result<(),
     let file_path = conversations_dir.join(format!("{}.json", conversation_id));
     
     if !file_path.exists() {
-        return Err(format!("Conversation {} not found", conversation_id));
+        return Err(AppError::not_found(format!("Conversation {} not found", conversation_id)));
     }
     
     fs::remove_file(&file_path)
-        .map_err(|e| format!("Failed to delete conversation file: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to delete conversation file: {}", e)))?;
     
     Ok(())
 }
 
-pub fn delete_all_conversations(app: &AppHandle) -> Result<(), String> {
+pub fn delete_all_conversations(app: &AppHandle) -> Result<(), AppError> {
     let conversations_dir = get_conversations_dir(app);
     
     if !conversations_dir.exists() {
@@ -137,23 +137,23 @@ pub fn delete_all_conversations(app: &AppHandle) -> Result<(), String> {
     
     // Read all JSON files in the conversations directory and delete them
     let entries = fs::read_dir(&conversations_dir)
-        .map_err(|e| format!("Failed to read conversations directory: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to read conversations directory: {}", e)))?;
     
     for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read directory entry: {}", e))?;
+        let entry = entry.map_err(|e| AppError::storage(format!("Failed to read directory entry: {}", e)))?;
         let path = entry.path();
         
         // Only delete .json files
         if path.extension().and_then(|s| s.to_str()) == Some("json") {
             fs::remove_file(&path)
-                .map_err(|e| format!("Failed to delete conversation file {}: {}", path.display(), e))?;
+                .map_err(|e| AppError::storage(format!("Failed to delete conversation file {}: {}", path.display(), e)))?;
         }
     }
     
     Ok(())
 }
 
-pub fn get_conversation_history(app: &AppHandle) -> Result<Vec<ConversationMetadata>, String> {
+pub fn get_conversation_history(app: &AppHandle) -> Result<Vec<ConversationMetadata>, AppError> {
     let conversations = list_conversations(app)?;
     
     let metadata: Vec<ConversationMetadata> = conversations
@@ -211,19 +211,26 @@ pub fn generate_title_from_message(message: &str) -> String {
     }
 }
 
-pub fn create_conversation(app: &AppHandle, user_message: &str) -> Result<String, String> {
+pub fn create_conversation(app: &AppHandle, user_message: &str, images: Vec<ImageAttachment>, files: Vec<FileAttachment>, persona: Option<String>) -> Result<String, AppError> {
     let conversations_dir = get_conversations_dir(app);
     
     // Ensure the conversations directory exists
     fs::create_dir_all(&conversations_dir)
-        .map_err(|e| format!("Failed to create conversations directory: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to create conversations directory: {}", e)))?;
     
     // Generate conversation ID
     let conversation_id = generate_conversation_id();
     let iso_timestamp = get_iso_timestamp();
     
-    // Generate title from first message
-    let title = generate_title_from_message(user_message);
+    // Generate title from first message, or its attachments when there is no text
+    let title = match generate_title_from_message(user_message) {
+        title if title.is_empty() => files
+            .first()
+            .map(|file| file.name.clone())
+            .or_else(|| images.first().map(|image| image.name.clone()))
+            .unwrap_or_default(),
+        title => title,
+    };
     
     // Create the first message
     let first_message = Message {
@@ -231,6 +238,9 @@ pub fn create_conversation(app: &AppHandle, user_message: &str) -> Result<String
         content: user_message.to_string(),
         timestamp: iso_timestamp.clone(),
         complete: true,
+        images,
+        files,
+        ..Default::default()
     };
     
     // Create the conversation
@@ -240,6 +250,11 @@ pub fn create_conversation(app: &AppHandle, user_message: &str) -> Result<String
         created_at: iso_timestamp.clone(),
         updated_at: iso_timestamp,
         messages: vec![first_message],
+        generation: GenerationParams::default(),
+        persona,
+        context_policy: None,
+        summary: None,
+        extra_usage: Vec::new(),
     };
     
     // Save the conversation
@@ -0,0 +1,188 @@
+use std::io::{Cursor, Read};
+use std::panic;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::api::context::estimate_text_tokens;
+use crate::models::{AppError, ErrorKind, FileAttachment};
+
+const MAX_FILE_BYTES: usize = 20 * 1024 * 1024;
+const MAX_DOCUMENT_TOKENS: u64 = 50_000; // Leaves room for the conversation in most context windows
+
+// Source file extensions and the language name given to the model
+const CODE_EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"), ("py", "python"), ("js", "javascript"), ("mjs", "javascript"), ("ts", "typescript"),
+    ("jsx", "jsx"), ("tsx", "tsx"), ("svelte", "svelte"), ("vue", "vue"), ("go", "go"), ("java", "java"),
+    ("kt", "kotlin"), ("swift", "swift"), ("c", "c"), ("h", "c"), ("cpp", "cpp"), ("cc", "cpp"), ("hpp", "cpp"),
+    ("cs", "csharp"), ("rb", "ruby"), ("php", "php"), ("sh", "bash"), ("bash", "bash"), ("zsh", "zsh"),
+    ("ps1", "powershell"), ("sql", "sql"), ("html", "html"), ("css", "css"), ("scss", "scss"),
+    ("json", "json"), ("yaml", "yaml"), ("yml", "yaml"), ("toml", "toml"), ("xml", "xml"), ("lua", "lua"),
+    ("r", "r"), ("scala", "scala"), ("dart", "dart"), ("zig", "zig"), ("ex", "elixir"), ("hs", "haskell"),
+];
+
+fn unsupported(message: String) -> AppError {
+    AppError::new(ErrorKind::Unsupported, message)
+}
+
+// Extract the text of an attached file. The format is chosen by extension:
+// PDF, DOCX, Markdown, known source code, and any other file that is valid
+// UTF-8 text.
+pub fn extract_document(name: &str, data: &[u8]) -> Result<FileAttachment, AppError> {
+    if data.len() > MAX_FILE_BYTES {
+        return Err(unsupported(format!(
+            "{} is too large ({} MB, at most {} MB)",
+            name,
+            data.len() / (1024 * 1024),
+            MAX_FILE_BYTES / (1024 * 1024)
+        )));
+    }
+    
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_lowercase())
+        .unwrap_or_default();
+    
+    let (format, content) = match extension.as_str() {
+        "pdf" => ("pdf".to_string(), extract_pdf(name, data)?),
+        "docx" => ("docx".to_string(), extract_docx(name, data)?),
+        "md" | "markdown" => ("markdown".to_string(), decode_text(name, data)?),
+        _ => {
+            let format = CODE_EXTENSIONS
+                .iter()
+                .find(|(code_extension, _)| *code_extension == extension)
+                .map(|(_, language)| language.to_string())
+                .unwrap_or_else(|| "text".to_string());
+            (format, decode_text(name, data)?)
+        }
+    };
+    
+    let content = content.trim().to_string();
+    if content.is_empty() {
+        return Err(unsupported(format!("{} has no text to attach", name)));
+    }
+    
+    let tokens = estimate_text_tokens(&content);
+    if tokens > MAX_DOCUMENT_TOKENS {
+        return Err(unsupported(format!(
+            "{} is too long (about {} tokens, at most {})",
+            name, tokens, MAX_DOCUMENT_TOKENS
+        )));
+    }
+    
+    Ok(FileAttachment {
+        name: name.to_string(),
+        format,
+        content,
+        tokens,
+    })
+}
+
+fn decode_text(name: &str, data: &[u8]) -> Result<String, AppError> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data); // UTF-8 byte order mark
+    String::from_utf8(data.to_vec())
+        .map_err(|_| unsupported(format!("Unsupported file type: {} is not a text, PDF or DOCX file", name)))
+}
+
+fn extract_pdf(name: &str, data: &[u8]) -> Result<String, AppError> {
+    // The PDF parser panics on some malformed files
+    let result = panic::catch_unwind(|| pdf_extract::extract_text_from_mem(data))
+        .map_err(|_| unsupported(format!("Failed to read {}: the PDF is malformed", name)))?;
+    
+    result.map_err(|e| unsupported(format!("Failed to read {}: {}", name, e)))
+}
+
+// The text of a DOCX is in word/document.xml: runs of `w:t` inside `w:p`
+// paragraphs, with tabs and line breaks as empty elements
+fn extract_docx(name: &str, data: &[u8]) -> Result<String, AppError> {
+    let invalid = |e: String| unsupported(format!("Failed to read {}: {}", name, e));
+    
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| invalid(e.to_string()))?;
+    // The uncompressed size is capped too, so a small archive can't expand
+    // into more than memory holds
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| invalid(e.to_string()))?
+        .take(MAX_FILE_BYTES as u64 + 1)
+        .read_to_string(&mut xml)
+        .map_err(|e| invalid(e.to_string()))?;
+    if xml.len() > MAX_FILE_BYTES {
+        return Err(unsupported(format!(
+            "{} is too large (more than {} MB uncompressed)",
+            name,
+            MAX_FILE_BYTES / (1024 * 1024)
+        )));
+    }
+    
+    let mut reader = Reader::from_str(&xml);
+    let mut text = String::new();
+    let mut in_text = false;
+    
+    loop {
+        match reader.read_event().map_err(|e| invalid(e.to_string()))? {
+            Event::Start(e) if e.name().as_ref() == b"w:t" => in_text = true,
+            Event::End(e) if e.name().as_ref() == b"w:t" => in_text = false,
+            Event::End(e) if e.name().as_ref() == b"w:p" => text.push('\n'),
+            Event::Empty(e) if e.name().as_ref() == b"w:tab" => text.push('\t'),
+            Event::Empty(e) if e.name().as_ref() == b"w:br" => text.push('\n'),
+            Event::Text(e) if in_text => text.push_str(&e.unescape().map_err(|e| invalid(e.to_string()))?),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    
+    fn docx(document_xml: &str) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        writer.start_file("word/document.xml", zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(document_xml.as_bytes()).unwrap();
+        writer.finish().unwrap().into_inner()
+    }
+    
+    #[test]
+    fn routes_by_extension() {
+        let code = extract_document("main.RS", b"fn main() {}\n").unwrap();
+        assert_eq!((code.format.as_str(), code.content.as_str()), ("rust", "fn main() {}"));
+        assert_eq!(extract_document("notes.md", b"# Notes").unwrap().format, "markdown");
+        assert_eq!(extract_document("data.csv", b"a,b").unwrap().format, "text");
+        assert_eq!(extract_document("README", b"hello").unwrap().format, "text");
+        
+        let document = docx(
+            "<w:document><w:body><w:p><w:r><w:t>One</w:t><w:tab/><w:t>two &amp; three</w:t></w:r></w:p>\
+             <w:p><w:r><w:t>Four</w:t></w:r></w:p></w:body></w:document>",
+        );
+        let file = extract_document("report.docx", &document).unwrap();
+        assert_eq!((file.format.as_str(), file.content.as_str()), ("docx", "One\ttwo & three\nFour"));
+        
+        let error = extract_document("image.png", &[0x89, b'P', b'N', b'G', 0xff, 0xfe]).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Unsupported);
+    }
+    
+    #[test]
+    fn strips_the_byte_order_mark() {
+        let file = extract_document("notes.txt", b"\xEF\xBB\xBFhello").unwrap();
+        assert_eq!(file.content, "hello");
+    }
+    
+    #[test]
+    fn refuses_files_without_text() {
+        let error = extract_document("empty.txt", b" \n\t ").unwrap_err();
+        assert_eq!(error.message, "empty.txt has no text to attach");
+    }
+    
+    #[test]
+    fn limits_document_tokens() {
+        let limit = MAX_DOCUMENT_TOKENS as usize * 4;
+        assert_eq!(extract_document("ok.txt", "a".repeat(limit).as_bytes()).unwrap().tokens, MAX_DOCUMENT_TOKENS);
+        
+        let error = extract_document("long.txt", "a".repeat(limit + 4).as_bytes()).unwrap_err();
+        assert!(error.message.starts_with("long.txt is too long"), "{}", error.message);
+    }
+}
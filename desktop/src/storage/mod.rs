@@ -1,3 +1,5 @@
 pub mod settings;
 pub mod conversations;
 
+pub mod attachments;
+pub mod documents;
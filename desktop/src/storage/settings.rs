@@ -1,11 +1,11 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::PathBuf;
 use tauri::{AppHandle, Manager};
 use serde_json;
 
-use crate::models::{Settings, default_shortcut};
-use crate::encryption::{load_api_keys_from_encrypted, save_api_keys_to_encrypted, store_api_key_in_encrypted};
+use crate::models::{AppError, ContextPolicy, McpServer, NetworkSettings, Persona, ProviderParams, Settings, WebSearchSettings, default_shortcut};
+use crate::encryption::{is_secret_field, load_api_keys_from_encrypted, save_api_keys_to_encrypted, store_api_key_in_encrypted};
 
 pub fn get_config_path(app: &AppHandle) -> PathBuf {
     app.path()
@@ -14,7 +14,7 @@ pub fn get_config_path(app: &AppHandle) -> PathBuf {
         .join("config.json")
 }
 
-pub fn load_settings(app: &AppHandle) -> Result<HashMap<String, serde_json::Value>, String> {
+pub fn load_settings(app: &AppHandle) -> Result<HashMap<String, serde_json::Value>, AppError> {
     let config_path = get_config_path(app);
     
     // Load settings from file or use defaults
@@ -26,9 +26,9 @@ pub fn load_settings(app: &AppHandle) -> Result<HashMap<String, serde_json::Valu
         match fs::read_to_string(&config_path) {
             Ok(content) => {
                 serde_json::from_str(&content)
-                    .map_err(|e| format!("Failed to parse config.json: {}", e))?
+                    .map_err(|e| AppError::corrupt(format!("Failed to parse config.json: {}", e)))?
             }
-            Err(e) => return Err(format!("Failed to read config.json: {}", e)),
+            Err(e) => return Err(AppError::storage(format!("Failed to read config.json: {}", e))),
         }
     };
     
@@ -40,6 +40,16 @@ pub fn load_settings(app: &AppHandle) -> Result<HashMap<String, serde_json::Valu
         }
     }
     
+    // Fill in defaults for providers added since config.json was written, and
+    // move settings made for older versions to their replacements
+    if let Some(provider_params) = settings_json.get_mut("provider-params") {
+        if let Ok(mut parsed) = serde_json::from_value::<ProviderParams>(provider_params.clone()) {
+            parsed.openai.migrate_search_model();
+            *provider_params = serde_json::to_value(parsed)
+                .map_err(|e| format!("Failed to serialize provider-params: {}", e))?;
+        }
+    }
+    
     // Load API keys from encrypted storage into JSON
     load_api_keys_from_encrypted(app, &mut settings_json, "")?;
     
@@ -51,11 +61,11 @@ pub fn load_settings(app: &AppHandle) -> Result<HashMap<String, serde_json::Valu
 }
 
 // Helper function to migrate API keys from JSON to encrypted storage (one-time migration)
-fn migrate_api_keys_to_encrypted(app: &AppHandle, value: &mut serde_json::Value) -> Result<(), String> {
+fn migrate_api_keys_to_encrypted(app: &AppHandle, value: &mut serde_json::Value) -> Result<(), AppError> {
     migrate_api_keys_to_encrypted_recursive(app, value, "provider-params")
 }
 
-fn migrate_api_keys_to_encrypted_recursive(app: &AppHandle, value: &mut serde_json::Value, path_prefix: &str) -> Result<(), String> {
+fn migrate_api_keys_to_encrypted_recursive(app: &AppHandle, value: &mut serde_json::Value, path_prefix: &str) -> Result<(), AppError> {
     match value {
         serde_json::Value::Object(map) => {
             for (key, val) in map.iter_mut() {
@@ -65,7 +75,7 @@ fn migrate_api_keys_to_encrypted_recursive(app: &AppHandle, value: &mut serde_js
                     format!("{}.{}", path_prefix, key)
                 };
                 
-                if key.ends_with("_key") {
+                if is_secret_field(path_prefix, key) {
                     if let serde_json::Value::String(s) = val {
                         // If we have a non-empty API key in JSON, migrate it to encrypted storage
                         if !s.is_empty() && s != "__NOT_STORED_HERE__" {
@@ -85,7 +95,7 @@ fn migrate_api_keys_to_encrypted_recursive(app: &AppHandle, value: &mut serde_js
     Ok(())
 }
 
-pub fn save_settings(app: &AppHandle, settings_map: HashMap<String, serde_json::Value>) -> Result<(), String> {
+pub fn save_settings(app: &AppHandle, settings_map: HashMap<String, serde_json::Value>) -> Result<(), AppError> {
     let config_path = get_config_path(app);
     
     // Convert HashMap to JSON Value for processing
@@ -102,13 +112,19 @@ pub fn save_settings(app: &AppHandle, settings_map: HashMap<String, serde_json::
         .map(|s| s.to_string())
         .unwrap_or_else(|| "ollama".to_string());
     
+    let fallback_providers: Vec<String> = match settings_map.get("fallback-providers") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse fallback-providers: {}", e)))?,
+        None => Vec::new(),
+    };
+    
     let provider_params_value = settings_json
         .get("provider-params")
         .cloned()
         .unwrap_or_else(|| serde_json::to_value(crate::models::ProviderParams::default()).unwrap());
     
     let provider_params: crate::models::ProviderParams = serde_json::from_value(provider_params_value)
-        .map_err(|e| format!("Failed to parse provider-params: {}", e))?;
+        .map_err(|e| AppError::config(format!("Failed to parse provider-params: {}", e)))?;
     
     let conversation_history = settings_map
         .get("conversation-history")
@@ -132,8 +148,50 @@ pub fn save_settings(app: &AppHandle, settings_map: HashMap<String, serde_json::
         .map(|s| s.to_string())
         .unwrap_or_else(|| crate::models::default_theme());
     
+    let personas: Vec<Persona> = match settings_map.get("personas") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse personas: {}", e)))?,
+        None => Vec::new(),
+    };
+    
+    let context_policy: ContextPolicy = match settings_map.get("context-policy") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse context-policy: {}", e)))?,
+        None => ContextPolicy::default(),
+    };
+    
+    let network: NetworkSettings = match settings_map.get("network") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse network settings: {}", e)))?,
+        None => NetworkSettings::default(),
+    };
+    
+    let web_search: WebSearchSettings = match settings_map.get("web-search") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse web-search settings: {}", e)))?,
+        None => WebSearchSettings::default(),
+    };
+    
+    let tools_enabled = settings_map
+        .get("tools-enabled")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    
+    let reasoning_in_history = settings_map
+        .get("reasoning-in-history")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    
+    // Read after encryption, like provider-params, so secrets are markers
+    let mcp_servers: BTreeMap<String, McpServer> = match settings_json.get("mcp-servers") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse mcp-servers: {}", e)))?,
+        None => BTreeMap::new(),
+    };
+    
     let settings = Settings {
         provider,
+        fallback_providers,
         provider_params,
         conversation_history,
         keyboard_shortcut,
@@ -141,11 +199,18 @@ pub fn save_settings(app: &AppHandle, settings_map: HashMap<String, serde_json::
         theme,
         window_x: None,
         window_y: None,
+        personas,
+        context_policy,
+        network,
+        tools_enabled,
+        mcp_servers,
+        web_search,
+        reasoning_in_history,
     };
     
     // Ensure the directory exists
     if let Some(parent) = config_path.parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create config directory: {}", e))?;
+        fs::create_dir_all(parent).map_err(|e| AppError::storage(format!("Failed to create config directory: {}", e)))?;
     }
     
     // Serialize to JSON (this will use the serde rename attributes)
@@ -155,8 +220,74 @@ pub fn save_settings(app: &AppHandle, settings_map: HashMap<String, serde_json::
     
     // Write to file
     fs::write(&config_path, json)
-        .map_err(|e| format!("Failed to write config.json: {}", e))?;
+        .map_err(|e| AppError::storage(format!("Failed to write config.json: {}", e)))?;
     
     Ok(())
 }
 
+pub fn load_personas(app: &AppHandle) -> Result<Vec<Persona>, AppError> {
+    let settings = load_settings(app)?;
+    match settings.get("personas") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse personas: {}", e))),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub fn find_persona(app: &AppHandle, name: &str) -> Result<Option<Persona>, AppError> {
+    Ok(load_personas(app)?.into_iter().find(|p| p.name == name))
+}
+
+pub fn save_personas(app: &AppHandle, personas: &[Persona]) -> Result<(), AppError> {
+    let mut settings = load_settings(app)?;
+    let value = serde_json::to_value(personas)
+        .map_err(|e| format!("Failed to serialize personas: {}", e))?;
+    settings.insert("personas".to_string(), value);
+    save_settings(app, settings)
+}
+
+pub fn load_context_policy(app: &AppHandle) -> Result<ContextPolicy, AppError> {
+    let settings = load_settings(app)?;
+    match settings.get("context-policy") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse context-policy: {}", e))),
+        None => Ok(ContextPolicy::default()),
+    }
+}
+
+pub fn load_network_settings(app: &AppHandle) -> Result<NetworkSettings, AppError> {
+    let settings = load_settings(app)?;
+    match settings.get("network") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse network settings: {}", e))),
+        None => Ok(NetworkSettings::default()),
+    }
+}
+
+// Whether earlier answers' reasoning is sent back to the model
+pub fn load_reasoning_in_history(app: &AppHandle) -> Result<bool, AppError> {
+    let settings = load_settings(app)?;
+    Ok(settings
+        .get("reasoning-in-history")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false))
+}
+
+// MCP servers by name, with their secrets decrypted
+pub fn load_mcp_servers(app: &AppHandle) -> Result<BTreeMap<String, McpServer>, AppError> {
+    let settings = load_settings(app)?;
+    match settings.get("mcp-servers") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse mcp-servers: {}", e))),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+pub fn load_web_search_settings(app: &AppHandle) -> Result<WebSearchSettings, AppError> {
+    let settings = load_settings(app)?;
+    match settings.get("web-search") {
+        Some(value) => serde_json::from_value(value.clone())
+            .map_err(|e| AppError::config(format!("Failed to parse web-search settings: {}", e))),
+        None => Ok(WebSearchSettings::default()),
+    }
+}
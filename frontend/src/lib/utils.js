@@ -39,3 +39,57 @@ export function formatTimestamp(timestamp) {
   return date.toLocaleDateString();
 }
 
+// Message of an error returned by a command, which is an object
+// {kind, message, provider, retryable, hint} for backend errors
+export function errorMessage(error) {
+  return error?.message ?? String(error);
+}
+
+// Whether the fix for an error is in the settings
+export function errorNeedsSettings(error) {
+  return ['auth', 'config', 'model-not-found', 'unsupported'].includes(error?.kind);
+}
+
+// Image types the backend accepts as attachments
+export const IMAGE_TYPES = ['image/png', 'image/jpeg', 'image/gif', 'image/webp'];
+
+// Documents offered in the file picker; the backend also accepts any other
+// UTF-8 text file
+export const DOCUMENT_TYPES = [
+  '.pdf', '.docx', '.txt', '.md', '.markdown', '.csv', '.log', '.json', '.yaml', '.yml', '.toml', '.xml', '.html', '.css',
+  '.rs', '.py', '.js', '.ts', '.jsx', '.tsx', '.svelte', '.go', '.java', '.kt', '.swift', '.c', '.h', '.cpp', '.cs', '.rb', '.php', '.sh', '.sql'
+];
+
+// Read a file or clipboard blob as a data URL
+export function readFileAsDataUrl(file) {
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve(reader.result);
+    reader.onerror = () => reject(reader.error);
+    reader.readAsDataURL(file);
+  });
+}
+
+// Image files pasted into an input
+export function pastedImages(event) {
+  return Array.from(event.clipboardData?.items ?? [])
+    .filter((item) => item.kind === 'file' && item.type.startsWith('image/'))
+    .map((item) => item.getAsFile())
+    .filter(Boolean);
+}
+
+// Human-readable text for a status notice sent on a stream channel
+export function describeStreamStatus(status) {
+  switch (status.status) {
+    case 'retrying':
+      return `${status.reason} - retrying in ${Math.ceil(status.delayMs / 1000)}s (attempt ${status.attempt} of ${status.maxAttempts})`;
+    case 'fallback':
+      return `${status.from} unavailable - trying ${status.to}`;
+    case 'tool-call':
+      return `Running tool ${status.name}`;
+    case 'tool-approval':
+      return `Waiting for approval to run ${status.name}`;
+    default:
+      return '';
+  }
+}